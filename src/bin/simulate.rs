@@ -0,0 +1,61 @@
+//! Plays the checked-in solutions of levels without a window, and reports the results.
+//! Exits with a failure if a solution does not get the robot to the goal.
+//!
+//! Usage: `cargo run --bin simulate [LEVEL_IDENTIFIER...]`
+//! Without arguments, every solution in `assets/levels/solutions` is played.
+
+use std::process::ExitCode;
+
+use bevy_jam_5::headless::{simulate, solution_path, solved_levels, Sequence, SOLUTIONS_DIR};
+
+fn main() -> ExitCode {
+    let mut levels = std::env::args().skip(1).collect::<Vec<_>>();
+    if levels.is_empty() {
+        levels = match solved_levels() {
+            Ok(levels) => levels,
+            Err(e) => {
+                eprintln!("Failed to list solutions in {SOLUTIONS_DIR}: {e}");
+                return ExitCode::FAILURE;
+            }
+        };
+        // checking nothing is not a success
+        if levels.is_empty() {
+            eprintln!("No solutions in {SOLUTIONS_DIR}");
+            return ExitCode::FAILURE;
+        }
+    }
+
+    let mut failed = false;
+    for level in &levels {
+        let path = solution_path(level);
+        let sequence = match Sequence::load(&path) {
+            Ok(sequence) => sequence,
+            Err(e) => {
+                println!("{level}: failed to load {path}: {e}");
+                failed = true;
+                continue;
+            }
+        };
+
+        match simulate(level, &sequence) {
+            Ok(report) => {
+                let status = if report.reached_goal { "ok" } else { "FAILED" };
+                println!(
+                    "{level}: {status} (position {:.1}, {:.1} at {:.2}s)",
+                    report.final_position.x, report.final_position.y, report.elapsed
+                );
+                failed |= !report.reached_goal;
+            }
+            Err(e) => {
+                println!("{level}: {e}");
+                failed = true;
+            }
+        }
+    }
+
+    if failed {
+        ExitCode::FAILURE
+    } else {
+        ExitCode::SUCCESS
+    }
+}
@@ -0,0 +1,183 @@
+//! Ghost robots replaying earlier loops of the tape.
+//!
+//! Each time the tape plays to its end, a ghost of each robot is left behind with the notes of
+//! that loop. The ghosts replay their notes alongside the player on every following loop, as solid
+//! as the player, so that the robot can stand on them. Older ghosts fade out and the oldest are
+//! removed. Stopping or restarting the tape clears them, so that only the loops played through
+//! since are left behind.
+
+use avian2d::prelude::*;
+use bevy::prelude::*;
+use bevy_tnua::{prelude::TnuaController, TnuaUserControlsSystemSet};
+
+use super::player::{
+    play_actions, robot_bundle, robot_sprite, walk, Facing, JumpHeld, Player, RobotIndex,
+};
+use super::rewind::History;
+use super::spawn::level::NextLevel;
+use crate::screen::{
+    playing::{
+        sequencer::{sequence::Sequence, LoopCompleted, Sequencer, TapeRestarted},
+        SequencerState,
+    },
+    Screen,
+};
+
+pub(super) fn plugin(app: &mut App) {
+    app.init_resource::<Ghosts>();
+    app.add_systems(OnExit(Screen::Playing), clear_ghosts);
+    app.observe(spawn_ghost)
+        .observe(clear_ghosts_on_next_level)
+        .observe(clear_ghosts_on_restart);
+
+    app.add_systems(
+        FixedUpdate,
+        replay_ghosts
+            .run_if(in_state(SequencerState::Playing).and_then(resource_exists::<Sequencer>))
+            .in_set(TnuaUserControlsSystemSet),
+    );
+    app.add_systems(Update, fade_ghosts.run_if(resource_changed::<Ghosts>));
+}
+
+/// A robot replaying the notes of an earlier loop.
+#[derive(Component, Debug)]
+pub struct Ghost {
+    sequence: Sequence,
+    /// The robot whose notes are replayed.
+    robot: RobotIndex,
+}
+
+/// The ghosts of the level, a group of one per robot for each loop, oldest first.
+#[derive(Resource, Debug, Default)]
+struct Ghosts(Vec<Vec<Entity>>);
+
+// Ghosts older than this many loops are removed.
+const MAX_GHOSTS: usize = 3;
+
+// Opacity of the newest ghost. Older ones fade out from there.
+const GHOST_ALPHA: f32 = 0.4;
+
+fn spawn_ghost(
+    trigger: Trigger<LoopCompleted>,
+    mut commands: Commands,
+    mut ghosts: ResMut<Ghosts>,
+    history: Res<History>,
+    players: Query<(Entity, &RobotIndex, &GlobalTransform), With<Player>>,
+) {
+    let mut group = vec![];
+    for (player, &robot, transform) in &players {
+        // Starts where the player starts every loop
+        let Some(position) = history.initial_position(player) else {
+            continue;
+        };
+
+        let ghost = commands
+            .spawn((
+                Name::new("Ghost"),
+                Ghost {
+                    sequence: trigger.event().0.clone(),
+                    robot,
+                },
+                robot_bundle(),
+                position,
+                SpatialBundle::from_transform(Transform::from_translation(
+                    position.0.extend(transform.translation().z),
+                )),
+                StateScoped(Screen::Playing),
+            ))
+            .with_children(|children| {
+                children.spawn(robot_sprite(Color::NONE));
+            })
+            .id();
+        group.push(ghost);
+    }
+    if group.is_empty() {
+        return;
+    }
+
+    ghosts.0.push(group);
+    if ghosts.0.len() > MAX_GHOSTS {
+        for oldest in ghosts.0.remove(0) {
+            commands.entity(oldest).despawn_recursive();
+        }
+    }
+}
+
+fn clear_ghosts(mut commands: Commands, mut ghosts: ResMut<Ghosts>) {
+    for ghost in ghosts.0.drain(..).flatten() {
+        if let Some(entity) = commands.get_entity(ghost) {
+            entity.despawn_recursive();
+        }
+    }
+}
+
+// The ghosts replayed the previous level
+fn clear_ghosts_on_next_level(
+    _trigger: Trigger<NextLevel>,
+    commands: Commands,
+    ghosts: ResMut<Ghosts>,
+) {
+    clear_ghosts(commands, ghosts);
+}
+
+// The ghosts replayed loops the player chose to start over from
+fn clear_ghosts_on_restart(
+    _trigger: Trigger<TapeRestarted>,
+    commands: Commands,
+    ghosts: ResMut<Ghosts>,
+) {
+    clear_ghosts(commands, ghosts);
+}
+
+// All ghosts play along with the tape, each from its own notes
+fn replay_ghosts(
+    mut commands: Commands,
+    sequencer: Res<Sequencer>,
+    mut ghosts: Query<(
+        Entity,
+        &Ghost,
+        &mut TnuaController,
+        &mut JumpHeld,
+        &mut Facing,
+        &Position,
+    )>,
+    spatial_query: SpatialQuery,
+) {
+    for (entity, ghost, mut controller, mut jump_held, mut facing, position) in &mut ghosts {
+        let notes = ghost
+            .sequence
+            .playing_at(sequencer.play_pos())
+            .into_iter()
+            .filter(|note| note.robot == ghost.robot.0)
+            .collect::<Vec<_>>();
+        walk(&notes, &mut controller, &mut facing);
+        play_actions(
+            &mut commands,
+            &spatial_query,
+            &notes,
+            entity,
+            &mut controller,
+            &mut jump_held,
+            position,
+            &facing,
+        );
+    }
+}
+
+fn fade_ghosts(
+    ghosts: Res<Ghosts>,
+    children: Query<&Children, With<Ghost>>,
+    mut sprites: Query<&mut Sprite>,
+) {
+    // the newest ghosts are the last ones
+    for (age, group) in ghosts.0.iter().rev().enumerate() {
+        let alpha = GHOST_ALPHA * (1.0 - age as f32 / MAX_GHOSTS as f32);
+        for &ghost in group {
+            for &child in children.get(ghost).into_iter().flatten() {
+                if let Ok(mut sprite) = sprites.get_mut(child) {
+                    sprite.color = Color::srgba(0.6, 0.9, 1.0, alpha);
+                }
+            }
+        }
+    }
+}
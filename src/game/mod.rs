@@ -4,8 +4,10 @@ use bevy::prelude::*;
 
 pub mod assets;
 pub mod audio;
+mod ghost;
 pub mod spawn;
-mod player;
+pub mod player;
+mod rewind;
 
 pub(super) fn plugin(app: &mut App) {
     app.add_plugins((
@@ -13,5 +15,7 @@ pub(super) fn plugin(app: &mut App) {
         assets::plugin,
         spawn::plugin,
         player::plugin,
+        rewind::plugin,
+        ghost::plugin,
     ));
 }
@@ -1,5 +1,8 @@
 use avian2d::prelude::*;
-use bevy::prelude::*;
+use bevy::{
+    prelude::*,
+    utils::{HashMap, HashSet},
+};
 // use bevy_debug_text_overlay::screen_print;
 use bevy_ecs_ldtk::prelude::*;
 use bevy_tnua::{
@@ -11,92 +14,312 @@ use bevy_tnua::{
 };
 use bevy_tnua_avian2d::{TnuaAvian2dPlugin, TnuaAvian2dSensorShape};
 
+use super::rewind::Rewindable;
 use crate::screen::playing::{
-    sequencer::{NoteKind, PlayingNotes},
+    complete::LevelResults,
+    sequencer::{Note, NoteKind, PlayingNotes},
     SequencerState,
 };
 
 pub(super) fn plugin(app: &mut App) {
     // app.observe(spawn_player);
 
+    // Physics, Tnua and the sequencer all advance in the fixed timestep,
+    // so that the same notes always give the same trajectory regardless of frame rate.
     app.add_plugins((
-        PhysicsPlugins::default(),
+        PhysicsPlugins::new(FixedPostUpdate),
         // PhysicsDebugPlugin::default(),
-        TnuaControllerPlugin::default(),
-        TnuaAvian2dPlugin::default(),
+        TnuaControllerPlugin::new(FixedUpdate),
+        TnuaAvian2dPlugin::new(FixedUpdate),
     ))
     .insert_resource(Gravity(Vec2::NEG_Y * 100.0));
+    // Avian's own clock accumulates real time, which would step physics more or less often
+    // than the fixed timestep. Instead, physics steps exactly once per fixed tick.
+    let timestep = app
+        .world()
+        .get_resource::<Time<Fixed>>()
+        .map_or_else(|| Time::<Fixed>::default().timestep(), Time::timestep);
+    app.insert_resource(Time::<Physics>::from_timestep(TimestepMode::FixedOnce {
+        delta: timestep,
+    }));
 
     // these probably should belong to spawn::level
-    app.register_ldtk_entity::<Player>("Player")
+    app.register_ldtk_entity::<PlayerBundle>("Player")
         .observe(spawn_player);
+    app.register_ldtk_entity::<Goal>("Goal").observe(spawn_goal);
     app.register_ldtk_int_cell::<WallBundle>(1)
         .observe(spawn_wall);
+    app.register_ldtk_int_cell::<BreakableBundle>(2)
+        .observe(spawn_breakable)
+        .observe(break_on_hit)
+        .observe(on_broken)
+        .observe(on_repaired);
+    app.add_systems(Update, merge_wall_colliders);
 
     app.add_systems(
-        Update,
+        FixedUpdate,
         run_played_note
             .run_if(in_state(SequencerState::Playing))
             .in_set(TnuaUserControlsSystemSet),
     );
 
-    // Tnua control must be in the Update schedule
     app.add_systems(
-        Update,
+        FixedUpdate,
+        // only the first time, as more fixed steps may run before the state changes
+        check_goal.run_if(
+            in_state(SequencerState::Playing).and_then(not(resource_exists::<LevelResults>)),
+        ),
+    );
+
+    // Tnua control must be in the same schedule as `TnuaControllerPlugin`
+    app.add_systems(
+        FixedUpdate,
         (
-            player_auto_movement.run_if(
-                state_changed::<SequencerState>.and_then(in_state(SequencerState::Playing)),
-            ),
-            player_auto_movement_stop.run_if(
-                state_changed::<SequencerState>.and_then(not(in_state(SequencerState::Playing))),
-            ),
+            // Wait and Backward notes change the walk every frame they are played
+            player_auto_movement.run_if(in_state(SequencerState::Playing)),
+            // every tick, as the state may change in a frame without any
+            player_auto_movement_stop.run_if(not(in_state(SequencerState::Playing))),
         )
             .in_set(TnuaUserControlsSystemSet),
     );
+
+    app.add_systems(FixedUpdate, update_robot_collisions);
 }
 
-#[derive(Component, Debug, Clone, Default, LdtkEntity)]
+#[derive(Component, Debug, Clone, Default)]
 pub struct Player {}
 
-fn spawn_player(trigger: Trigger<OnAdd, Player>, mut commands: Commands) {
+#[derive(Bundle, Default, LdtkEntity)]
+struct PlayerBundle {
+    player: Player,
+    #[from_entity_instance]
+    robot: RobotIndex,
+}
+
+/// Which of the robots of the level a [`Player`] is, counting from `0`.
+/// Each robot is played by its own tracks in the sequencer, and only acts on their notes.
+#[derive(Component, Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct RobotIndex(pub usize);
+
+/// Identifier of the field of the LDtk Player entity holding its [`RobotIndex`].
+/// Players without it are the first robot.
+const ROBOT_FIELD: &str = "Robot";
+
+impl From<&EntityInstance> for RobotIndex {
+    fn from(instance: &EntityInstance) -> Self {
+        let index = instance.get_int_field(ROBOT_FIELD).copied().unwrap_or(0);
+        Self(index.max(0) as usize)
+    }
+}
+
+impl RobotIndex {
+    /// Color of the robot, and of its tracks in the sequencer when there are several robots.
+    pub fn color(self) -> Color {
+        const COLORS: [Color; 4] = [
+            Color::srgb(0.0, 1.0, 0.0),
+            Color::srgb(1.0, 0.55, 0.0),
+            Color::srgb(0.3, 0.6, 1.0),
+            Color::srgb(1.0, 0.35, 0.8),
+        ];
+        COLORS[self.0 % COLORS.len()]
+    }
+}
+
+/// A robot: the [`Player`], or a ghost replaying an earlier loop of the tape (see `game::ghost`).
+#[derive(Component, Debug, Clone, Copy, Default)]
+pub struct Robot;
+
+#[derive(Clone, Copy)]
+pub(super) enum GameLayer {
+    /// Walls and everything else.
+    Default,
+    Robot,
+}
+
+// avian's derive macro expects a `2d`/`3d` feature on the crate using it
+impl PhysicsLayer for GameLayer {
+    fn to_bits(&self) -> u32 {
+        1 << *self as u32
+    }
+
+    fn all_bits() -> u32 {
+        0b11
+    }
+}
+
+/// The robot clears the level by touching this.
+#[derive(Component, Debug, Clone, Default, LdtkEntity)]
+pub struct Goal {}
+
+/// Horizontal direction the robot is walking or would walk in. Either `1.0` (right) or `-1.0` (left).
+#[derive(Component, Debug, Clone, Copy)]
+pub struct Facing(pub f32);
+
+impl Default for Facing {
+    fn default() -> Self {
+        Self(1.0)
+    }
+}
+
+/// Whether a jump note was played for the robot on the last tick. Tnua remembers a held jump
+/// so as not to jump again on landing, but doesn't tell, so it's kept here for `game::rewind`.
+#[derive(Component, Debug, Clone, Copy, Default)]
+pub struct JumpHeld(pub bool);
+
+const WALK_SPEED: f32 = 40.0;
+
+/// Triggered when the robot reaches the goal of the level.
+#[derive(Event, Debug)]
+pub struct GoalReached;
+
+/// Triggered on entities hit by the robot's attack.
+#[derive(Event, Debug)]
+pub struct Hit;
+
+fn robot_collider() -> Collider {
+    Collider::round_rectangle(13.0, 13.0, 1.0)
+}
+
+/// Physics and control of a robot, shared by the player and the ghosts.
+pub(super) fn robot_bundle() -> impl Bundle {
+    let collider = robot_collider();
+    (
+        Robot,
+        RigidBody::Dynamic,
+        collider.clone(),
+        CollisionLayers::new(GameLayer::Robot, [GameLayer::Default, GameLayer::Robot]),
+        TnuaControllerBundle::default(),
+        TnuaAvian2dSensorShape(collider),
+        Facing::default(),
+        JumpHeld::default(),
+        Rewindable,
+        CollidingEntities::default(),
+    )
+}
+
+pub(super) fn robot_sprite(color: Color) -> SpriteBundle {
+    SpriteBundle {
+        sprite: Sprite {
+            color,
+            custom_size: Some(Vec2::splat(16.0)),
+            ..default()
+        },
+        ..default()
+    }
+}
+
+fn spawn_player(
+    trigger: Trigger<OnAdd, Player>,
+    mut commands: Commands,
+    robots: Query<&RobotIndex>,
+) {
     let entity = trigger.entity();
+    let robot = robots.get(entity).copied().unwrap_or_default();
 
     // note: at this point, bevy_ecs_ldtk have not added Transform yet
 
-    let collider = Collider::round_rectangle(13.0, 13.0, 1.0);
-
     commands
         .entity(entity)
-        .insert((
-            RigidBody::Dynamic,
-            collider.clone(),
-            TnuaControllerBundle::default(),
-            TnuaAvian2dSensorShape(collider),
-        ))
+        .insert(robot_bundle())
+        .with_children(|children| {
+            children.spawn(robot_sprite(robot.color().with_alpha(0.5)));
+        });
+}
+
+/// Robots overlapping each other pass through each other until they are apart, and then
+/// collide, so that they can stand on each other. All robots start a loop at the same place.
+/// This only depends on where the robots are, so the same notes still give the same result.
+fn update_robot_collisions(
+    mut commands: Commands,
+    // the spatial query reads the collision layers too
+    robots: Query<(Entity, &Position, &CollisionLayers), With<Robot>>,
+    spatial_query: SpatialQuery,
+) {
+    // resting on each other is not overlapping
+    let overlap = Collider::rectangle(9.0, 9.0);
+    for (entity, position, layers) in &robots {
+        let overlapping = !spatial_query
+            .shape_intersections(
+                &overlap,
+                position.0,
+                0.0,
+                SpatialQueryFilter::from_mask(GameLayer::Robot).with_excluded_entities([entity]),
+            )
+            .is_empty();
+        let wanted = if overlapping {
+            CollisionLayers::new(GameLayer::Robot, [GameLayer::Default])
+        } else {
+            CollisionLayers::new(GameLayer::Robot, [GameLayer::Default, GameLayer::Robot])
+        };
+        if *layers != wanted {
+            commands.entity(entity).insert(wanted);
+        }
+    }
+}
+
+fn spawn_goal(trigger: Trigger<OnAdd, Goal>, mut commands: Commands) {
+    commands
+        .entity(trigger.entity())
+        .insert((RigidBody::Static, Collider::rectangle(12.0, 12.0), Sensor))
         .with_children(|children| {
             children.spawn(SpriteBundle {
                 sprite: Sprite {
-                    color: Color::srgba(0.0, 1.0, 0.0, 0.5),
-                    custom_size: Some(Vec2::splat(16.0)),
+                    color: Color::srgba(0.95, 0.79, 0.3, 0.8),
+                    custom_size: Some(Vec2::splat(12.0)),
                     ..default()
                 },
-                ..Default::default()
+                ..default()
             });
         });
 }
 
-fn player_auto_movement(mut player_query: Query<&mut TnuaController, With<Player>>) {
-    for mut controller in &mut player_query {
-        controller.basis(TnuaBuiltinWalk {
-            desired_velocity: Vec3::new(40.0, 0.0, 0.0),
-            float_height: 4.0,
-            max_slope: std::f32::consts::FRAC_PI_4,
-            ..default()
-        });
+fn check_goal(
+    mut commands: Commands,
+    players: Query<&CollidingEntities, With<Player>>,
+    goals: Query<(), With<Goal>>,
+) {
+    let reached = players
+        .iter()
+        .any(|colliding| colliding.iter().any(|&entity| goals.contains(entity)));
+    if reached {
+        commands.trigger(GoalReached);
     }
 }
 
-fn player_auto_movement_stop(mut player_query: Query<&mut TnuaController, With<Player>>) {
+fn player_auto_movement(
+    playing_notes: Res<PlayingNotes>,
+    mut player_query: Query<(&RobotIndex, &mut TnuaController, &mut Facing), With<Player>>,
+) {
+    for (&robot, mut controller, mut facing) in &mut player_query {
+        walk(&playing_notes.of(robot.0), &mut controller, &mut facing);
+    }
+}
+
+/// Walks a robot the way `notes` being played make it.
+pub(super) fn walk(notes: &[Note], controller: &mut TnuaController, facing: &mut Facing) {
+    let playing = |kind| notes.iter().any(|note| note.kind == kind);
+    let direction = if playing(NoteKind::Backward) {
+        -1.0
+    } else {
+        1.0
+    };
+    let speed = if playing(NoteKind::Wait) {
+        0.0
+    } else {
+        WALK_SPEED
+    };
+
+    facing.0 = direction;
+    controller.basis(TnuaBuiltinWalk {
+        desired_velocity: Vec3::new(direction * speed, 0.0, 0.0),
+        float_height: 4.0,
+        max_slope: std::f32::consts::FRAC_PI_4,
+        ..default()
+    });
+}
+
+// Ghosts stop along with the player
+fn player_auto_movement_stop(mut player_query: Query<&mut TnuaController, With<Robot>>) {
     for mut controller in &mut player_query {
         controller.basis(TnuaBuiltinWalk {
             desired_velocity: Vec3::new(0.0, 0.0, 0.0),
@@ -115,6 +338,27 @@ struct WallBundle {
 #[derive(Default, Component)]
 struct Wall;
 
+#[derive(Default, Bundle, LdtkIntCell)]
+struct BreakableBundle {
+    wall: Wall,
+    breakable: Breakable,
+    rewindable: Rewindable,
+}
+
+/// A wall that is destroyed when hit by an attack.
+#[derive(Default, Component)]
+struct Breakable;
+
+/// A [`Breakable`] wall that has been hit. It is kept around (without collider)
+/// so that rewinding can bring it back.
+#[derive(Component)]
+pub(super) struct Broken;
+
+// setting 16.0 as the size of the wall will upset the physics engine
+fn wall_collider() -> Collider {
+    Collider::rectangle(15.9, 15.9)
+}
+
 /// Attaches Avian collider to a spawned wall entitiy.
 fn spawn_wall(trigger: Trigger<OnAdd, Wall>, mut commands: Commands, coords: Query<&GridCoords>) {
     let entity = trigger.entity();
@@ -127,29 +371,265 @@ fn spawn_wall(trigger: Trigger<OnAdd, Wall>, mut commands: Commands, coords: Que
 
     commands.entity(entity).insert((
         RigidBody::Static,
-        // setting 16.0 as the size of the wall will upset the physics engine
-        // TODO: merge consecutive walls
-        Collider::rectangle(15.9, 15.9),
+        // replaced by `merge_wall_colliders` once the whole level is spawned
+        wall_collider(),
         Transform::from_translation(Vec3::new(coords.x as f32 * 16., coords.y as f32 * 16., 0.0)),
     ));
 }
 
+/// Replaces the colliders of plain wall cells with fewer, larger ones once a level is spawned,
+/// so that the robot doesn't snag on the seams between cells.
+/// Breakable walls keep their own colliders, as they are removed one at a time.
+fn merge_wall_colliders(
+    mut commands: Commands,
+    mut level_events: EventReader<LevelEvent>,
+    levels: Query<(Entity, &LevelIid)>,
+    walls: Query<(Entity, &GridCoords, &Parent), (With<Wall>, Without<Breakable>)>,
+    parents: Query<&Parent>,
+) {
+    for event in level_events.read() {
+        let LevelEvent::Spawned(spawned_iid) = event else {
+            continue;
+        };
+        let Some((level, _)) = levels.iter().find(|(_, iid)| *iid == spawned_iid) else {
+            continue;
+        };
+
+        // Wall cells of the level, by the layer they are in
+        let mut layers: HashMap<Entity, HashSet<IVec2>> = HashMap::default();
+        for (entity, coords, layer) in &walls {
+            if parents.get(layer.get()).ok().map(Parent::get) != Some(level) {
+                continue;
+            }
+            commands.entity(entity).remove::<(Collider, RigidBody)>();
+            layers
+                .entry(layer.get())
+                .or_default()
+                .insert(IVec2::new(coords.x, coords.y));
+        }
+
+        for (layer, cells) in layers {
+            commands.entity(layer).with_children(|children| {
+                for (min, max) in merge_cells(cells) {
+                    // same placement as the cell colliders in `spawn_wall`
+                    let center = (min + max).as_vec2() * 8.0;
+                    let size = (max - min + IVec2::ONE).as_vec2() * 16.0 - 0.1;
+                    children.spawn((
+                        Name::new("Wall Collider"),
+                        RigidBody::Static,
+                        Collider::rectangle(size.x, size.y),
+                        TransformBundle::from_transform(Transform::from_translation(
+                            center.extend(0.0),
+                        )),
+                    ));
+                }
+            });
+        }
+    }
+}
+
+/// Covers `cells` with as few rectangles as a greedy search finds.
+/// Each rectangle is given by its minimum and maximum cells, inclusive.
+fn merge_cells(mut cells: HashSet<IVec2>) -> Vec<(IVec2, IVec2)> {
+    let mut starts = cells.iter().copied().collect::<Vec<_>>();
+    starts.sort_by_key(|cell| (cell.y, cell.x));
+
+    let mut rects = vec![];
+    for min in starts {
+        if !cells.contains(&min) {
+            continue;
+        }
+
+        // Grow to the right, then upwards while the whole row is walls
+        let mut max = min;
+        while cells.contains(&(max + IVec2::X)) {
+            max.x += 1;
+        }
+        while (min.x..=max.x).all(|x| cells.contains(&IVec2::new(x, max.y + 1))) {
+            max.y += 1;
+        }
+
+        for y in min.y..=max.y {
+            for x in min.x..=max.x {
+                cells.remove(&IVec2::new(x, y));
+            }
+        }
+        rects.push((min, max));
+    }
+    rects
+}
+
+/// Gives breakable walls a look, since the LDtk auto-tiling rules only cover plain walls.
+fn spawn_breakable(trigger: Trigger<OnAdd, Breakable>, mut commands: Commands) {
+    let mut entity = commands.entity(trigger.entity());
+    // so that hiding a broken wall also hides its sprite
+    entity.insert(VisibilityBundle::default());
+    entity.with_children(|children| {
+        children.spawn(SpriteBundle {
+            sprite: Sprite {
+                color: Color::srgb(0.56, 0.34, 0.23),
+                custom_size: Some(Vec2::splat(16.0)),
+                ..default()
+            },
+            ..default()
+        });
+    });
+}
+
+fn break_on_hit(
+    trigger: Trigger<Hit>,
+    mut commands: Commands,
+    breakable: Query<(), (With<Breakable>, Without<Broken>)>,
+) {
+    let entity = trigger.entity();
+    if breakable.contains(entity) {
+        commands.entity(entity).insert(Broken);
+    }
+}
+
+fn on_broken(trigger: Trigger<OnAdd, Broken>, mut commands: Commands) {
+    commands
+        .entity(trigger.entity())
+        .remove::<Collider>()
+        .insert(Visibility::Hidden);
+}
+
+// Rewinding to before the wall was hit
+fn on_repaired(trigger: Trigger<OnRemove, Broken>, mut commands: Commands) {
+    // `OnRemove` is also triggered when the level is despawned
+    if let Some(mut entity) = commands.get_entity(trigger.entity()) {
+        entity.try_insert((wall_collider(), Visibility::Inherited));
+    }
+}
+
 fn run_played_note(
+    mut commands: Commands,
     playing_notes: Res<PlayingNotes>,
-    mut player: Query<&mut TnuaController, With<Player>>,
+    mut player: Query<
+        (
+            Entity,
+            &RobotIndex,
+            &mut TnuaController,
+            &mut JumpHeld,
+            &Position,
+            &Facing,
+        ),
+        With<Player>,
+    >,
+    spatial_query: SpatialQuery,
 ) {
-    for note in &playing_notes.0 {
+    // each robot only plays the notes of its own tracks
+    for (entity, &robot, mut controller, mut jump_held, position, facing) in &mut player {
+        play_actions(
+            &mut commands,
+            &spatial_query,
+            &playing_notes.of(robot.0),
+            entity,
+            &mut controller,
+            &mut jump_held,
+            position,
+            facing,
+        );
+    }
+}
+
+/// Makes a robot act on the jump and attack notes in `notes`.
+pub(super) fn play_actions(
+    commands: &mut Commands,
+    spatial_query: &SpatialQuery,
+    notes: &[Note],
+    entity: Entity,
+    controller: &mut TnuaController,
+    jump_held: &mut JumpHeld,
+    position: &Position,
+    facing: &Facing,
+) {
+    jump_held.0 = notes.iter().any(|note| note.kind == NoteKind::Jump);
+    for note in notes {
         // debug!("Playing note: {:?}", note);
-        let action = match note.kind {
-            NoteKind::Jump => TnuaBuiltinJump {
-                height: 38.0,
-                ..default()
-            },
-        };
+        match note.kind {
+            NoteKind::Jump => {
+                controller.action(TnuaBuiltinJump {
+                    height: 38.0,
+                    ..default()
+                });
+            }
+            NoteKind::Attack => {
+                // Hit everything overlapping a box right in front of the robot
+                let hitbox = Collider::rectangle(12.0, 12.0);
+                let hit = spatial_query.shape_intersections(
+                    &hitbox,
+                    position.0 + Vec2::X * facing.0 * 14.0,
+                    0.0,
+                    SpatialQueryFilter::from_excluded_entities([entity]),
+                );
+                if !hit.is_empty() {
+                    commands.trigger_targets(Hit, hit);
+                }
+            }
+            // Handled by `walk`
+            NoteKind::Wait | NoteKind::Backward => {}
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
 
-        // there may be a puzzle with multiple player characters...
-        for mut controller in &mut player {
-            controller.action(action.clone());
+    fn cells(rows: &[&str]) -> HashSet<IVec2> {
+        rows.iter()
+            .rev()
+            .enumerate()
+            .flat_map(|(y, row)| {
+                row.chars()
+                    .enumerate()
+                    .filter(|&(_, c)| c == '#')
+                    .map(move |(x, _)| IVec2::new(x as i32, y as i32))
+            })
+            .collect()
+    }
+
+    /// Checks that `rects` cover exactly `cells`, each cell once.
+    fn assert_covers(rects: &[(IVec2, IVec2)], cells: &HashSet<IVec2>) {
+        let mut covered = HashSet::default();
+        for &(min, max) in rects {
+            for y in min.y..=max.y {
+                for x in min.x..=max.x {
+                    let cell = IVec2::new(x, y);
+                    assert!(cells.contains(&cell), "{cell} is not a wall");
+                    assert!(covered.insert(cell), "{cell} is covered twice");
+                }
+            }
         }
+        assert_eq!(covered.len(), cells.len());
+    }
+
+    #[test]
+    fn merges_a_block_into_one_rectangle() {
+        let block = cells(&["###", "###"]);
+        let rects = merge_cells(block.clone());
+        assert_eq!(rects, vec![(IVec2::new(0, 0), IVec2::new(2, 1))]);
+        assert_covers(&rects, &block);
+    }
+
+    #[test]
+    fn covers_a_level_outline() {
+        let level = cells(&[
+            "######", //
+            "#....#", //
+            "#.##.#", //
+            "#..#.#", //
+            "######",
+        ]);
+        let rects = merge_cells(level.clone());
+        assert_covers(&rects, &level);
+        // the floor, the walls, the ceiling and the two steps
+        assert_eq!(rects.len(), 6);
+    }
+
+    #[test]
+    fn merges_nothing_into_nothing() {
+        assert!(merge_cells(HashSet::default()).is_empty());
     }
 }
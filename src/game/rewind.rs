@@ -0,0 +1,330 @@
+//! Records the state of the world while the sequencer plays,
+//! and restores it while the tape is seeked.
+//!
+//! The Tnua controllers of the robots can't be saved or restored, only replaced with new ones,
+//! which rebuild their state from the robots' bodies and the notes fed to them. That is exact
+//! only while a robot stands on something that doesn't move, with no action running or held.
+//! So once a seek ends, the world is restored at the latest frame where all robots were like
+//! that, and the tape plays on from there (see [`Sequencer::resume_from`]).
+
+use avian2d::prelude::*;
+use bevy::prelude::*;
+use bevy_tnua::{builtins::TnuaBuiltinWalk, prelude::TnuaController};
+
+use super::{
+    player::{Broken, JumpHeld, Robot},
+    spawn::level::NextLevel,
+};
+use crate::screen::{
+    playing::{
+        sequencer::{Scrub, Sequencer},
+        SequencerState,
+    },
+    Screen,
+};
+
+pub(super) fn plugin(app: &mut App) {
+    app.init_resource::<History>();
+    app.add_systems(OnExit(Screen::Playing), clear_history);
+    app.observe(clear_history_on_next_level);
+
+    // Each frame is recorded before the tick that advances the play position,
+    // so restoring it and playing on gives the same result as the original play.
+    // The headless simulation plays without the sequencer UI, and never seeks.
+    app.add_systems(
+        FixedPreUpdate,
+        record_history
+            .run_if(in_state(SequencerState::Playing).and_then(resource_exists::<Sequencer>)),
+    );
+
+    // Physics is paused while the world follows the seek bar
+    app.add_systems(OnEnter(SequencerState::Seeking), pause_physics);
+    // ...and frozen while the level results are shown
+    app.add_systems(OnEnter(SequencerState::Cleared), pause_physics);
+    app.add_systems(OnExit(SequencerState::Cleared), unpause_physics);
+    // ...and while the tape is paused
+    app.add_systems(OnEnter(SequencerState::Paused), pause_physics);
+    app.add_systems(OnExit(SequencerState::Paused), unpause_physics);
+    app.add_systems(
+        Update,
+        follow_seek.run_if(in_state(SequencerState::Seeking)),
+    );
+    // ...and right away while it's scrubbed, so the world previews the scrubbed position
+    app.observe(restore_on_scrub);
+    app.add_systems(
+        OnExit(SequencerState::Seeking),
+        (restore_history, truncate_history, unpause_physics).chain(),
+    );
+}
+
+/// Entities whose state is recorded and restored by seeking.
+#[derive(Component, Default, Clone, Copy)]
+pub struct Rewindable;
+
+/// Snapshots of rewindable entities, ordered by play position.
+#[derive(Resource, Default)]
+pub struct History {
+    frames: Vec<Frame>,
+}
+
+struct Frame {
+    /// Play position of the sequencer when this frame was recorded.
+    time: f32,
+    states: Vec<(Entity, EntityState)>,
+}
+
+#[derive(Clone, Copy)]
+struct EntityState {
+    position: Position,
+    rotation: Rotation,
+    linear_velocity: LinearVelocity,
+    angular_velocity: AngularVelocity,
+    broken: bool,
+    controller: Option<ControllerState>,
+}
+
+/// What a robot's Tnua controller was doing, as far as Tnua tells.
+#[derive(Clone, Copy)]
+struct ControllerState {
+    /// Whether it has been fed anything since it was created.
+    fed: bool,
+    /// The action running, if any.
+    action: Option<&'static str>,
+    /// See [`JumpHeld`].
+    jump_held: bool,
+    /// Whether the robot stands on something that doesn't move.
+    standing_on_ground: bool,
+}
+
+impl ControllerState {
+    /// Whether a new controller fed the same notes would carry on the same way.
+    fn rebuildable(&self) -> bool {
+        !self.fed || (self.action.is_none() && !self.jump_held && self.standing_on_ground)
+    }
+}
+
+impl Frame {
+    fn rebuildable(&self) -> bool {
+        self.states.iter().all(|(_, state)| {
+            state
+                .controller
+                .as_ref()
+                .is_none_or(ControllerState::rebuildable)
+        })
+    }
+}
+
+impl History {
+    /// The latest frame recorded at or before `time`.
+    fn frame_at(&self, time: f32) -> Option<&Frame> {
+        let idx = self.frames.partition_point(|frame| frame.time <= time);
+        // fall back to the first frame if seeked before anything was recorded
+        self.frames.get(idx.saturating_sub(1))
+    }
+
+    /// The latest frame recorded at or before `time` that the world can be restored to exactly.
+    fn rebuildable_frame_at(&self, time: f32) -> Option<&Frame> {
+        let idx = self.frames.partition_point(|frame| frame.time <= time);
+        self.frames[..idx]
+            .iter()
+            .rev()
+            .find(|frame| frame.rebuildable())
+            // the tape always starts playing from where the robots are
+            .or(self.frames.first())
+    }
+
+    /// Where `entity` was when the tape first played, if it was recorded then.
+    pub(super) fn initial_position(&self, entity: Entity) -> Option<Position> {
+        let (_, state) = self
+            .frames
+            .first()?
+            .states
+            .iter()
+            .find(|(recorded, _)| *recorded == entity)?;
+        Some(state.position)
+    }
+
+    /// Forgets frames after `time`, as they will be recorded again.
+    fn truncate_after(&mut self, time: f32) {
+        let idx = self.frames.partition_point(|frame| frame.time <= time);
+        self.frames.truncate(idx.max(1));
+    }
+}
+
+fn clear_history(mut history: ResMut<History>) {
+    history.frames.clear();
+}
+
+// The entities of the previous level are gone
+fn clear_history_on_next_level(_trigger: Trigger<NextLevel>, mut history: ResMut<History>) {
+    history.frames.clear();
+}
+
+fn record_history(
+    mut history: ResMut<History>,
+    sequencer: Res<Sequencer>,
+    rewindables: Query<
+        (
+            Entity,
+            &Position,
+            &Rotation,
+            &LinearVelocity,
+            &AngularVelocity,
+            Has<Broken>,
+            Option<(&TnuaController, &JumpHeld)>,
+        ),
+        With<Rewindable>,
+    >,
+    robots: Query<(), With<Robot>>,
+) {
+    let time = sequencer.play_pos();
+
+    // the sequencer may have been seeked back without anything recorded in between
+    history.truncate_after(time);
+
+    let states = rewindables
+        .iter()
+        .map(
+            |(
+                entity,
+                &position,
+                &rotation,
+                &linear_velocity,
+                &angular_velocity,
+                broken,
+                controller,
+            )| {
+                let controller = controller.map(|(controller, jump_held)| {
+                    let standing_on = controller
+                        .concrete_basis::<TnuaBuiltinWalk>()
+                        .and_then(|(_, state)| state.standing_on_entity());
+                    ControllerState {
+                        fed: controller.basis_name().is_some(),
+                        action: controller.action_name(),
+                        jump_held: jump_held.0,
+                        // other robots move
+                        standing_on_ground: standing_on.is_some_and(|on| !robots.contains(on)),
+                    }
+                });
+                (
+                    entity,
+                    EntityState {
+                        position,
+                        rotation,
+                        linear_velocity,
+                        angular_velocity,
+                        broken,
+                        controller,
+                    },
+                )
+            },
+        )
+        .collect();
+
+    history.frames.push(Frame { time, states });
+}
+
+type RewindableQuery<'w, 's> = Query<
+    'w,
+    's,
+    (
+        &'static mut Position,
+        &'static mut Rotation,
+        &'static mut LinearVelocity,
+        &'static mut AngularVelocity,
+        Option<&'static mut TnuaController>,
+        Has<Broken>,
+    ),
+    With<Rewindable>,
+>;
+
+// The world follows the play position while seeking
+fn follow_seek(
+    mut commands: Commands,
+    history: Res<History>,
+    sequencer: Res<Sequencer>,
+    mut rewindables: RewindableQuery,
+) {
+    if let Some(frame) = history.frame_at(sequencer.play_pos()) {
+        restore_frame(&mut commands, frame, &mut rewindables);
+    }
+}
+
+fn restore_on_scrub(
+    trigger: Trigger<Scrub>,
+    mut commands: Commands,
+    history: Res<History>,
+    mut rewindables: RewindableQuery,
+) {
+    if let Some(frame) = history.frame_at(trigger.event().0) {
+        restore_frame(&mut commands, frame, &mut rewindables);
+    }
+}
+
+// Once seeked, the world is restored where playing on from gives the same result
+// as the original play, and the tape resumes from there
+fn restore_history(
+    mut commands: Commands,
+    history: Res<History>,
+    sequencer: Option<ResMut<Sequencer>>,
+    mut seq_state_next: ResMut<NextState<SequencerState>>,
+    mut rewindables: RewindableQuery,
+) {
+    // `OnExit(SequencerState::Seeking)` also runs when leaving the screen
+    let Some(mut sequencer) = sequencer else {
+        return;
+    };
+    let Some(frame) = history.rebuildable_frame_at(sequencer.play_pos()) else {
+        return;
+    };
+    restore_frame(&mut commands, frame, &mut rewindables);
+    sequencer.resume_from(frame.time, &mut seq_state_next);
+}
+
+/// Puts the rewindable entities back to how they were in `frame`.
+fn restore_frame(commands: &mut Commands, frame: &Frame, rewindables: &mut RewindableQuery) {
+    for (entity, state) in &frame.states {
+        let Ok((
+            mut position,
+            mut rotation,
+            mut linear_velocity,
+            mut angular_velocity,
+            controller,
+            broken,
+        )) = rewindables.get_mut(*entity)
+        else {
+            continue;
+        };
+
+        *position = state.position;
+        *rotation = state.rotation;
+        *linear_velocity = state.linear_velocity;
+        *angular_velocity = state.angular_velocity;
+
+        // The new controller is fed again from the notes on the next tick. Unless the frame is
+        // rebuildable (see `ControllerState`), this is only good for previewing the frame.
+        if let Some(mut controller) = controller {
+            *controller = TnuaController::default();
+        }
+
+        if state.broken && !broken {
+            commands.entity(*entity).insert(Broken);
+        } else if !state.broken && broken {
+            commands.entity(*entity).remove::<Broken>();
+        }
+    }
+}
+
+fn truncate_history(mut history: ResMut<History>, sequencer: Option<Res<Sequencer>>) {
+    if let Some(sequencer) = sequencer {
+        history.truncate_after(sequencer.play_pos());
+    }
+}
+
+fn pause_physics(mut time: ResMut<Time<Physics>>) {
+    time.pause();
+}
+
+fn unpause_physics(mut time: ResMut<Time<Physics>>) {
+    time.unpause();
+}
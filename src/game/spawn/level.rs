@@ -1,7 +1,11 @@
 //! Spawn the main level by triggering other observers.
 
 use bevy::prelude::*;
-use bevy_ecs_ldtk::{LdtkWorldBundle, LevelSelection};
+use bevy_ecs_ldtk::{
+    assets::{LdtkProject, LevelIndices},
+    ldtk::{raw_level_accessor::RawLevelAccessor, Level},
+    LdtkWorldBundle, LevelSelection,
+};
 
 use crate::{
     game::assets::{HandleMap, LdtkKey},
@@ -12,6 +16,7 @@ use crate::{
 
 pub(super) fn plugin(app: &mut App) {
     app.observe(spawn_level);
+    app.observe(next_level);
 
     app.insert_resource(LevelSelection::index(0));
 }
@@ -34,3 +39,50 @@ fn spawn_level(
         })
         .insert(StateScoped(Screen::Playing));
 }
+
+/// Levels of the project in the order they are played,
+/// which is the order they are listed in LDtk.
+pub fn level_order(project: &LdtkProject) -> impl Iterator<Item = (LevelIndices, &Level)> {
+    project
+        .iter_raw_levels()
+        .enumerate()
+        .map(|(i, level)| (LevelIndices::in_root(i), level))
+}
+
+/// The level after `selection`, or `None` if it is the last one.
+pub fn next_level_selection(
+    project: &LdtkProject,
+    selection: &LevelSelection,
+) -> Option<LevelSelection> {
+    let mut levels = level_order(project);
+    levels.find(|(indices, level)| selection.is_match(indices, level))?;
+    let (_, next) = levels.next()?;
+    Some(LevelSelection::iid(next.iid.clone()))
+}
+
+/// Trigger this event to move on to the level after the current one.
+/// The notes and the history of the current level are cleared by other observers.
+#[derive(Event, Debug)]
+pub struct NextLevel;
+
+// bevy_ecs_ldtk despawns the current level and spawns the selected one
+fn next_level(
+    _trigger: Trigger<NextLevel>,
+    mut level_selection: ResMut<LevelSelection>,
+    ldtk_handles: Res<HandleMap<LdtkKey>>,
+    projects: Res<Assets<LdtkProject>>,
+    mut next_screen: ResMut<NextState<Screen>>,
+) {
+    let Some(project) = projects.get(&ldtk_handles[&LdtkKey::Level]) else {
+        warn!("LDtk project is not loaded");
+        return;
+    };
+
+    match next_level_selection(project, &level_selection) {
+        Some(next) => *level_selection = next,
+        None => {
+            // All levels are cleared; let the player pick one to improve on
+            next_screen.set(Screen::LevelSelect);
+        }
+    }
+}
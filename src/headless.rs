@@ -0,0 +1,293 @@
+//! Runs a level without a window, feeding a saved note sequence to the robot.
+//! This is used to check that the solutions of puzzles still work.
+
+use std::{fmt, fs, io, time::Duration};
+
+use avian2d::prelude::*;
+use bevy::{
+    app::{PluginsState, SubApp},
+    asset::AssetMetaCheck,
+    log::LogPlugin,
+    prelude::*,
+    render::{settings::WgpuSettings, RenderApp, RenderPlugin},
+    time::TimeUpdateStrategy,
+    window::ExitCondition,
+    winit::WinitPlugin,
+};
+use bevy_ecs_ldtk::LevelSelection;
+use bevy_tnua::TnuaUserControlsSystemSet;
+
+pub use crate::screen::playing::sequencer::sequence::{
+    solution_path, Sequence, SequenceError, SOLUTIONS_DIR,
+};
+use crate::{
+    game::{
+        self,
+        assets::{HandleMap, LdtkKey},
+        player::{GoalReached, Player},
+        spawn::level::SpawnLevel,
+    },
+    screen::{
+        playing::{sequencer::PlayingNotes, SequencerState},
+        Screen,
+    },
+};
+
+/// Plays `sequence` on the level named `level_identifier` and reports what happened
+/// by the time the tape ends or the robot reaches the goal.
+pub fn simulate(
+    level_identifier: &str,
+    sequence: &Sequence,
+) -> Result<SimulationReport, SimulationError> {
+    simulate_at(
+        level_identifier,
+        sequence,
+        Duration::from_secs_f32(TIMESTEP),
+    )
+}
+
+/// Like [`simulate`], with each update advancing time by `frame_time`, as if the game ran at
+/// that frame rate. The fixed timestep stays the same, so this shouldn't change the results.
+pub fn simulate_at(
+    level_identifier: &str,
+    sequence: &Sequence,
+    frame_time: Duration,
+) -> Result<SimulationReport, SimulationError> {
+    let mut app = App::new();
+    app.add_plugins(HeadlessPlugin {
+        level_identifier: level_identifier.to_string(),
+        sequence: sequence.clone(),
+        frame_time,
+    });
+
+    // What `App::run` would do before the first update
+    while app.plugins_state() == PluginsState::Adding {
+        bevy::tasks::tick_global_task_pools_on_main_thread();
+    }
+    app.finish();
+    app.cleanup();
+
+    // Loading the level takes a few updates; the rest is bounded by the settling and the tape.
+    let play_time = SETTLE_STEPS as f32 * TIMESTEP + sequence.length;
+    let max_updates = MAX_LOADING_UPDATES + (play_time / frame_time.as_secs_f32()).ceil() as usize;
+    for _ in 0..max_updates {
+        app.update();
+        if let Some(report) = app.world().get_resource::<SimulationReport>() {
+            return Ok(report.clone());
+        }
+    }
+
+    Err(SimulationError::Timeout)
+}
+
+/// Identifiers of the levels that have a solution file in [`SOLUTIONS_DIR`], in order.
+pub fn solved_levels() -> io::Result<Vec<String>> {
+    let mut levels = vec![];
+    for entry in fs::read_dir(SOLUTIONS_DIR)? {
+        let path = entry?.path();
+        if path.extension().is_some_and(|ext| ext == "ron") {
+            if let Some(stem) = path.file_stem() {
+                levels.push(stem.to_string_lossy().into_owned());
+            }
+        }
+    }
+    levels.sort();
+    Ok(levels)
+}
+
+#[derive(Resource, Debug, Clone, PartialEq)]
+pub struct SimulationReport {
+    pub reached_goal: bool,
+    /// Position of the robot when the simulation ended.
+    pub final_position: Vec2,
+    /// Play position of the tape when the simulation ended, in seconds.
+    pub elapsed: f32,
+}
+
+#[derive(Debug)]
+pub enum SimulationError {
+    /// The level or the robot was never spawned.
+    Timeout,
+}
+
+impl fmt::Display for SimulationError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SimulationError::Timeout => write!(f, "the level was not spawned in time"),
+        }
+    }
+}
+
+impl std::error::Error for SimulationError {}
+
+// Updates allowed for loading assets and spawning the level.
+const MAX_LOADING_UPDATES: usize = 600;
+
+// Length of the fixed timestep, which updates advance time by unless told otherwise.
+const TIMESTEP: f32 = 1.0 / 64.0;
+
+/// The game mechanics without any window, UI or GPU.
+pub struct HeadlessPlugin {
+    pub level_identifier: String,
+    pub sequence: Sequence,
+    /// Time each update advances by.
+    pub frame_time: Duration,
+}
+
+impl Plugin for HeadlessPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_plugins(
+            DefaultPlugins
+                .set(AssetPlugin {
+                    meta_check: AssetMetaCheck::Never,
+                    ..default()
+                })
+                .set(WindowPlugin {
+                    primary_window: None,
+                    exit_condition: ExitCondition::DontExit,
+                    close_when_requested: false,
+                })
+                .set(RenderPlugin {
+                    render_creation: WgpuSettings {
+                        backends: None,
+                        ..default()
+                    }
+                    .into(),
+                    ..default()
+                })
+                .disable::<WinitPlugin>()
+                // so that `simulate` can be called more than once in a process
+                .disable::<LogPlugin>(),
+        );
+
+        app.insert_resource(TimeUpdateStrategy::ManualDuration(self.frame_time));
+        app.insert_resource(Time::<Fixed>::from_duration(Duration::from_secs_f32(
+            TIMESTEP,
+        )));
+
+        app.init_state::<Screen>();
+        app.enable_state_scoped_entities::<Screen>();
+        app.add_sub_state::<SequencerState>();
+        app.init_resource::<PlayingNotes>();
+
+        // `bevy_ecs_tilemap`, added by `bevy_ecs_ldtk`, expects the render sub-app,
+        // which isn't created without a GPU. Lend it a throwaway one.
+        app.insert_sub_app(RenderApp, SubApp::new());
+        app.add_plugins(game::plugin);
+        app.remove_sub_app(RenderApp);
+
+        // `game::plugin` selects the first level
+        app.insert_resource(LevelSelection::Identifier(self.level_identifier.clone()));
+        app.insert_resource(Tape {
+            sequence: self.sequence.clone(),
+            play_pos: 0.0,
+        });
+
+        app.add_systems(
+            Update,
+            enter_playing.run_if(in_state(Screen::Loading).and_then(ldtk_loaded)),
+        );
+        app.add_systems(OnEnter(Screen::Playing), spawn_level);
+        app.add_systems(
+            FixedUpdate,
+            start_tape.run_if(in_state(SequencerState::Stopped)),
+        );
+        app.add_systems(OnEnter(SequencerState::Playing), unpause_physics);
+        app.add_systems(
+            FixedUpdate,
+            advance_tape
+                .run_if(
+                    in_state(SequencerState::Playing)
+                        .and_then(not(resource_exists::<SimulationReport>)),
+                )
+                .before(TnuaUserControlsSystemSet),
+        );
+        app.observe(goal_reached);
+    }
+}
+
+/// The sequence being played, in place of the sequencer UI.
+#[derive(Resource)]
+struct Tape {
+    sequence: Sequence,
+    /// Play position in seconds.
+    play_pos: f32,
+}
+
+fn ldtk_loaded(asset_server: Res<AssetServer>, ldtk_handles: Res<HandleMap<LdtkKey>>) -> bool {
+    ldtk_handles.all_loaded(&asset_server)
+}
+
+fn enter_playing(mut next_screen: ResMut<NextState<Screen>>) {
+    next_screen.set(Screen::Playing);
+}
+
+fn spawn_level(mut commands: Commands) {
+    commands.trigger(SpawnLevel);
+}
+
+// Fixed steps the robots are left to settle in the level before the tape plays,
+// so that they start from rest whatever the frame rate.
+const SETTLE_STEPS: u32 = 128;
+
+// Start playing once the robot has settled in the world
+fn start_tape(
+    mut settled_steps: Local<u32>,
+    player: Query<(), (With<Player>, With<Position>)>,
+    mut physics_time: ResMut<Time<Physics>>,
+    mut seq_state_next: ResMut<NextState<SequencerState>>,
+) {
+    if player.is_empty() {
+        return;
+    }
+    *settled_steps += 1;
+    if *settled_steps >= SETTLE_STEPS {
+        // The state only changes on the next update, after more or fewer fixed steps
+        // depending on the frame rate. The world waits for it.
+        physics_time.pause();
+        seq_state_next.set(SequencerState::Playing);
+    }
+}
+
+fn unpause_physics(mut physics_time: ResMut<Time<Physics>>) {
+    physics_time.unpause();
+}
+
+fn advance_tape(
+    mut commands: Commands,
+    time: Res<Time>,
+    mut tape: ResMut<Tape>,
+    mut playing_notes: ResMut<PlayingNotes>,
+    player: Query<&Position, With<Player>>,
+) {
+    tape.play_pos += time.delta_seconds();
+
+    if tape.play_pos > tape.sequence.length {
+        commands.insert_resource(SimulationReport {
+            reached_goal: false,
+            final_position: player.iter().next().map_or(Vec2::ZERO, |pos| pos.0),
+            elapsed: tape.sequence.length,
+        });
+        return;
+    }
+
+    *playing_notes = PlayingNotes(tape.sequence.playing_at(tape.play_pos));
+}
+
+fn goal_reached(
+    _trigger: Trigger<GoalReached>,
+    mut commands: Commands,
+    tape: Res<Tape>,
+    report: Option<Res<SimulationReport>>,
+    player: Query<&Position, With<Player>>,
+) {
+    // more fixed steps may run in the same update, reaching the goal again
+    if report.is_some() {
+        return;
+    }
+    commands.insert_resource(SimulationReport {
+        reached_goal: true,
+        final_position: player.iter().next().map_or(Vec2::ZERO, |pos| pos.0),
+        elapsed: tape.play_pos,
+    });
+}
@@ -1,7 +1,11 @@
 #[cfg(feature = "dev")]
 mod dev_tools;
 mod game;
+pub mod headless;
+mod progress;
+mod save;
 mod screen;
+mod settings;
 mod ui;
 
 use bevy::{
@@ -10,7 +14,7 @@ use bevy::{
     log::LogPlugin,
     prelude::*, window::WindowResolution,
 };
-use bevy_video_glitch::{VideoGlitchPlugin, VideoGlitchSettings};
+use bevy_video_glitch::VideoGlitchPlugin;
 
 pub struct AppPlugin;
 
@@ -63,7 +67,14 @@ impl Plugin for AppPlugin {
         app.add_plugins(VideoGlitchPlugin);
 
         // Add other plugins.
-        app.add_plugins((game::plugin, screen::plugin, ui::plugin));
+        app.add_plugins((
+            game::plugin,
+            progress::plugin,
+            save::plugin,
+            screen::plugin,
+            settings::plugin,
+            ui::plugin,
+        ));
 
         // Enable dev tools for dev builds.
         #[cfg(feature = "dev")]
@@ -95,10 +106,6 @@ fn spawn_camera(mut commands: Commands) {
         // [ui node outlines](https://bevyengine.org/news/bevy-0-14/#ui-node-outline-gizmos)
         // for debugging. So it's good to have this here for future-proofing.
         IsDefaultUiCamera,
-        // TODO: add only during unwinding
-        VideoGlitchSettings {
-            intensity: 0.1,
-            ..default()
-        }
+        // `VideoGlitchSettings` is added only during seeking (see `screen::playing::glitch`)
     ));
 }
@@ -0,0 +1,126 @@
+//! The player's progress through the levels.
+
+use std::collections::BTreeMap;
+
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::screen::playing::sequencer::sequence::Sequence;
+
+pub(super) fn plugin(app: &mut App) {
+    app.init_resource::<Progress>();
+}
+
+/// Levels cleared so far, keyed by the level iid in the LDtk project.
+#[derive(Resource, Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Progress {
+    pub levels: BTreeMap<String, LevelRecord>,
+    /// The notes of the best clear of each level.
+    pub solutions: BTreeMap<String, Sequence>,
+}
+
+/// The best clear of a level.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct LevelRecord {
+    /// Play position when the goal was reached, in seconds.
+    pub time: f32,
+    /// Number of notes on the tape.
+    pub notes: usize,
+}
+
+impl LevelRecord {
+    /// Faster is better; with the same time, fewer notes is better.
+    pub fn is_better_than(&self, other: &LevelRecord) -> bool {
+        (self.time, self.notes) < (other.time, other.notes)
+    }
+}
+
+impl Progress {
+    pub fn is_completed(&self, level_iid: &str) -> bool {
+        self.levels.contains_key(level_iid)
+    }
+
+    pub fn best(&self, level_iid: &str) -> Option<&LevelRecord> {
+        self.levels.get(level_iid)
+    }
+
+    /// Records a clear of a level with the notes that cleared it, keeping the best one.
+    /// Returns whether it is a new best.
+    pub fn record(&mut self, level_iid: &str, record: LevelRecord, solution: Sequence) -> bool {
+        if let Some(best) = self.levels.get(level_iid) {
+            if !record.is_better_than(best) {
+                return false;
+            }
+        }
+        self.levels.insert(level_iid.to_string(), record);
+        self.solutions.insert(level_iid.to_string(), solution);
+        true
+    }
+
+    /// A level can be played once the level before it is completed.
+    /// `level_iids` lists all levels in play order.
+    pub fn is_unlocked<'a>(
+        &self,
+        level_iid: &str,
+        level_iids: impl IntoIterator<Item = &'a str>,
+    ) -> bool {
+        let mut previous = None;
+        for iid in level_iids {
+            if iid == level_iid {
+                return previous.is_none_or(|previous| self.is_completed(previous));
+            }
+            previous = Some(iid);
+        }
+        false
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const LEVELS: [&str; 3] = ["a", "b", "c"];
+
+    fn record(time: f32, notes: usize) -> LevelRecord {
+        LevelRecord { time, notes }
+    }
+
+    #[test]
+    fn unlocks_levels_in_order() {
+        let mut progress = Progress::default();
+        assert!(progress.is_unlocked("a", LEVELS));
+        assert!(!progress.is_unlocked("b", LEVELS));
+
+        progress.record("a", record(3.0, 2), default());
+        assert!(progress.is_unlocked("b", LEVELS));
+        assert!(!progress.is_unlocked("c", LEVELS));
+        // levels that aren't in the project are never unlocked
+        assert!(!progress.is_unlocked("d", LEVELS));
+    }
+
+    #[test]
+    fn keeps_the_best_record() {
+        let mut progress = Progress::default();
+        assert!(progress.record("a", record(3.0, 2), default()));
+        // slower
+        assert!(!progress.record("a", record(4.0, 1), default()));
+        // as fast with more notes
+        assert!(!progress.record("a", record(3.0, 3), default()));
+        // as fast with fewer notes
+        assert!(progress.record("a", record(3.0, 1), default()));
+        assert_eq!(progress.best("a"), Some(&record(3.0, 1)));
+    }
+
+    #[test]
+    fn keeps_the_solution_of_the_best_record() {
+        let solution = |length| Sequence {
+            length,
+            tracks: vec![],
+        };
+        let mut progress = Progress::default();
+        progress.record("a", record(3.0, 2), solution(8.0));
+        progress.record("a", record(4.0, 2), solution(12.0));
+        assert_eq!(progress.solutions.get("a"), Some(&solution(8.0)));
+    }
+}
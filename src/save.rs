@@ -0,0 +1,291 @@
+//! Saves the player's progress and settings, so that they survive a restart.
+//!
+//! The save data is a versioned RON document kept by a [`SaveStorage`].
+//! Native builds keep it in a file in the platform data directory.
+
+use std::{fmt, io};
+
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::{progress::Progress, settings::Settings};
+
+pub(super) fn plugin(app: &mut App) {
+    if let Some(storage) = default_storage() {
+        app.insert_resource(SaveBackend(storage));
+    } else {
+        warn!("No storage for save data; progress will not be saved");
+    }
+
+    app.add_systems(Startup, load_save.run_if(resource_exists::<SaveBackend>));
+    app.add_systems(
+        Last,
+        write_save.run_if(
+            resource_exists::<SaveBackend>
+                .and_then(resource_changed::<Progress>.or_else(resource_changed::<Settings>)),
+        ),
+    );
+}
+
+/// Where the save data is kept.
+pub trait SaveStorage: Send + Sync + 'static {
+    /// Reads the save data, or `None` if nothing has been saved yet.
+    fn read(&self) -> Result<Option<String>, SaveError>;
+
+    fn write(&self, data: &str) -> Result<(), SaveError>;
+}
+
+/// The storage used by this build.
+#[derive(Resource)]
+pub struct SaveBackend(pub Box<dyn SaveStorage>);
+
+/// A save file in the platform data directory.
+#[cfg(not(target_family = "wasm"))]
+pub struct FileStorage {
+    pub path: std::path::PathBuf,
+}
+
+#[cfg(not(target_family = "wasm"))]
+impl SaveStorage for FileStorage {
+    fn read(&self) -> Result<Option<String>, SaveError> {
+        match std::fs::read_to_string(&self.path) {
+            Ok(data) => Ok(Some(data)),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    fn write(&self, data: &str) -> Result<(), SaveError> {
+        if let Some(dir) = self.path.parent() {
+            std::fs::create_dir_all(dir)?;
+        }
+        // Write to a temporary file first so that a crash doesn't leave a truncated save
+        let tmp_path = self.path.with_extension("ron.tmp");
+        std::fs::write(&tmp_path, data)?;
+        std::fs::rename(&tmp_path, &self.path)?;
+        Ok(())
+    }
+}
+
+#[cfg(not(target_family = "wasm"))]
+fn default_storage() -> Option<Box<dyn SaveStorage>> {
+    let path = dirs::data_dir()?
+        .join(env!("CARGO_PKG_NAME"))
+        .join("save.ron");
+    Some(Box::new(FileStorage { path }))
+}
+
+// TODO: keep the save data in the browser's local storage
+#[cfg(target_family = "wasm")]
+fn default_storage() -> Option<Box<dyn SaveStorage>> {
+    None
+}
+
+/// Version of the save data written by this build.
+/// Bump this when a change can't be handled by `#[serde(default)]`,
+/// and upgrade the older data in [`SaveData::from_ron`].
+pub const SAVE_VERSION: u32 = 1;
+
+/// Everything that is saved.
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+#[serde(default)]
+pub struct SaveData {
+    pub version: u32,
+    pub progress: Progress,
+    pub settings: Settings,
+}
+
+/// Just enough of the save data to tell how to read the rest.
+#[derive(Deserialize)]
+struct SaveHeader {
+    /// Save data written before it was versioned has none.
+    #[serde(default)]
+    version: u32,
+}
+
+/// Save data written before it was versioned.
+#[derive(Deserialize, Default)]
+#[serde(default)]
+struct SaveDataV0 {
+    progress: Progress,
+    settings: Settings,
+}
+
+impl SaveDataV0 {
+    /// Only the version was added in version 1.
+    fn upgrade(self) -> SaveData {
+        SaveData {
+            version: 1,
+            progress: self.progress,
+            settings: self.settings,
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum SaveError {
+    Io(io::Error),
+    Serialize(ron::Error),
+    Parse(ron::error::SpannedError),
+    /// The save data was written by a newer build of the game, or is not save data at all.
+    UnsupportedVersion(u32),
+}
+
+impl fmt::Display for SaveError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SaveError::Io(e) => write!(f, "I/O error: {e}"),
+            SaveError::Serialize(e) => write!(f, "failed to serialize: {e}"),
+            SaveError::Parse(e) => write!(f, "failed to parse: {e}"),
+            SaveError::UnsupportedVersion(v) => write!(
+                f,
+                "save data version {v} is not supported (up to {SAVE_VERSION})"
+            ),
+        }
+    }
+}
+
+impl std::error::Error for SaveError {}
+
+impl From<io::Error> for SaveError {
+    fn from(e: io::Error) -> Self {
+        SaveError::Io(e)
+    }
+}
+
+impl From<ron::Error> for SaveError {
+    fn from(e: ron::Error) -> Self {
+        SaveError::Serialize(e)
+    }
+}
+
+impl From<ron::error::SpannedError> for SaveError {
+    fn from(e: ron::error::SpannedError) -> Self {
+        SaveError::Parse(e)
+    }
+}
+
+impl SaveData {
+    pub fn to_ron(&self) -> Result<String, SaveError> {
+        Ok(ron::ser::to_string_pretty(
+            self,
+            ron::ser::PrettyConfig::default(),
+        )?)
+    }
+
+    /// Parses save data of any version up to [`SAVE_VERSION`],
+    /// upgrading older data to the current version.
+    pub fn from_ron(s: &str) -> Result<Self, SaveError> {
+        let SaveHeader { version } = ron::from_str(s)?;
+        let data = match version {
+            // Older versions are parsed with their own types and upgraded here. Each `upgrade`
+            // converts to the next version, and should call its `upgrade` in turn.
+            0 => ron::from_str::<SaveDataV0>(s)?.upgrade(),
+            SAVE_VERSION => ron::from_str(s)?,
+            version => return Err(SaveError::UnsupportedVersion(version)),
+        };
+        Ok(SaveData {
+            version: SAVE_VERSION,
+            ..data
+        })
+    }
+}
+
+fn load_save(mut commands: Commands, backend: Res<SaveBackend>) {
+    let data = match backend.0.read() {
+        Ok(Some(data)) => data,
+        Ok(None) => return,
+        Err(e) => {
+            error!("Failed to read save data, progress will not be saved: {e}");
+            commands.remove_resource::<SaveBackend>();
+            return;
+        }
+    };
+
+    match SaveData::from_ron(&data) {
+        Ok(save) => {
+            commands.insert_resource(save.progress);
+            commands.insert_resource(save.settings);
+        }
+        Err(e) => {
+            // Don't overwrite what we couldn't read; it may still be recovered
+            error!("Failed to load save data, progress will not be saved: {e}");
+            commands.remove_resource::<SaveBackend>();
+        }
+    }
+}
+
+fn write_save(backend: Res<SaveBackend>, progress: Res<Progress>, settings: Res<Settings>) {
+    let save = SaveData {
+        version: SAVE_VERSION,
+        progress: progress.clone(),
+        settings: settings.clone(),
+    };
+    if let Err(e) = save.to_ron().and_then(|data| backend.0.write(&data)) {
+        error!("Failed to write save data: {e}");
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::progress::LevelRecord;
+
+    #[test]
+    fn upgrades_unversioned_data() {
+        let v0 = r#"(
+            progress: (
+                levels: {
+                    "level-a": (time: 4.5, notes: 3),
+                },
+            ),
+            settings: (
+                volume: 0.5,
+            ),
+        )"#;
+
+        let save = SaveData::from_ron(v0).unwrap();
+        assert_eq!(save.version, SAVE_VERSION);
+        assert_eq!(
+            save.progress.best("level-a"),
+            Some(&LevelRecord {
+                time: 4.5,
+                notes: 3
+            })
+        );
+        assert_eq!(save.settings.volume, 0.5);
+        // settings that weren't saved keep their defaults
+        assert_eq!(save.settings.key_bindings, Settings::default().key_bindings);
+    }
+
+    #[test]
+    fn reads_back_what_it_writes() {
+        let mut save = SaveData {
+            version: SAVE_VERSION,
+            ..default()
+        };
+        save.progress.record(
+            "level-a",
+            LevelRecord {
+                time: 2.0,
+                notes: 1,
+            },
+            default(),
+        );
+        save.settings.glitch_effect = false;
+
+        let read = SaveData::from_ron(&save.to_ron().unwrap()).unwrap();
+        assert_eq!(read.progress.levels, save.progress.levels);
+        assert_eq!(read.progress.solutions, save.progress.solutions);
+        assert_eq!(read.settings, save.settings);
+    }
+
+    #[test]
+    fn rejects_newer_versions() {
+        let newer = format!("(version: {})", SAVE_VERSION + 1);
+        assert!(matches!(
+            SaveData::from_ron(&newer),
+            Err(SaveError::UnsupportedVersion(version)) if version == SAVE_VERSION + 1
+        ));
+    }
+}
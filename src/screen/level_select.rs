@@ -0,0 +1,108 @@
+//! A screen to pick a level to play, showing the progress through the levels.
+
+use bevy::{prelude::*, ui::Val::*};
+use bevy_ecs_ldtk::{assets::LdtkProject, LevelSelection};
+
+use super::Screen;
+use crate::{
+    game::{
+        assets::{HandleMap, LdtkKey},
+        spawn::level::level_order,
+    },
+    progress::Progress,
+    ui::prelude::*,
+};
+
+pub(super) fn plugin(app: &mut App) {
+    app.add_systems(OnEnter(Screen::LevelSelect), enter_level_select);
+
+    app.register_type::<LevelSelectAction>();
+    app.add_systems(
+        Update,
+        handle_level_select_action.run_if(in_state(Screen::LevelSelect)),
+    );
+}
+
+#[derive(Component, Debug, Clone, PartialEq, Eq, Reflect)]
+#[reflect(Component)]
+enum LevelSelectAction {
+    /// Play the level with this iid.
+    Play(String),
+    Back,
+}
+
+fn enter_level_select(
+    mut commands: Commands,
+    ldtk_handles: Res<HandleMap<LdtkKey>>,
+    projects: Res<Assets<LdtkProject>>,
+    progress: Res<Progress>,
+) {
+    // Assets are loaded before the title screen
+    let project = projects
+        .get(&ldtk_handles[&LdtkKey::Level])
+        .expect("LDtk project is loaded");
+    let level_iids = level_order(project)
+        .map(|(_, level)| level.iid.as_str())
+        .collect::<Vec<_>>();
+
+    commands
+        .ui_root()
+        .insert(StateScoped(Screen::LevelSelect))
+        .with_children(|children| {
+            children.header("Select Level");
+
+            for (_, level) in level_order(project) {
+                let unlocked = progress.is_unlocked(&level.iid, level_iids.iter().copied());
+                let status = match progress.best(&level.iid) {
+                    Some(best) => format!("Cleared - {:.2}s, {} notes", best.time, best.notes),
+                    None if unlocked => "Not cleared".to_string(),
+                    None => "Locked".to_string(),
+                };
+
+                children
+                    .spawn((
+                        Name::new(format!("Level {}", level.identifier)),
+                        NodeBundle {
+                            style: Style {
+                                align_items: AlignItems::Center,
+                                column_gap: Px(10.0),
+                                ..default()
+                            },
+                            ..default()
+                        },
+                    ))
+                    .with_children(|children| {
+                        let mut button = children.button(level.identifier.clone());
+                        if unlocked {
+                            button.insert(LevelSelectAction::Play(level.iid.clone()));
+                        } else {
+                            // Locked levels don't react to the cursor, not even with sounds
+                            button
+                                .remove::<(Interaction, InteractionPalette)>()
+                                .insert(BackgroundColor(ui_palette::LOCKED_BUTTON_BACKGROUND));
+                        }
+                        children.label(status);
+                    });
+            }
+
+            children.button("Back").insert(LevelSelectAction::Back);
+        });
+}
+
+fn handle_level_select_action(
+    mut next_screen: ResMut<NextState<Screen>>,
+    mut button_query: InteractionQuery<&LevelSelectAction>,
+    mut level_selection: ResMut<LevelSelection>,
+) {
+    for (interaction, action) in &mut button_query {
+        if matches!(interaction, Interaction::Pressed) {
+            match action {
+                LevelSelectAction::Play(iid) => {
+                    *level_selection = LevelSelection::iid(iid.clone());
+                    next_screen.set(Screen::Playing);
+                }
+                LevelSelectAction::Back => next_screen.set(Screen::Title),
+            }
+        }
+    }
+}
@@ -1,6 +1,7 @@
 //! The game's main screen states and transitions between them.
 
 mod credits;
+mod level_select;
 mod loading;
 pub mod playing;
 mod splash;
@@ -16,6 +17,7 @@ pub(super) fn plugin(app: &mut App) {
         splash::plugin,
         loading::plugin,
         title::plugin,
+        level_select::plugin,
         credits::plugin,
         playing::plugin,
     ));
@@ -28,6 +30,7 @@ pub enum Screen {
     #[default]
     Loading,
     Title,
+    LevelSelect,
     Credits,
     Playing,
 }
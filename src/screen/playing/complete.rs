@@ -0,0 +1,161 @@
+//! The level-complete overlay, shown once the robot reaches the goal.
+
+use bevy::prelude::*;
+use bevy_ecs_ldtk::{assets::LdtkProject, LevelIid, LevelSelection};
+
+use super::{
+    sequencer::{Note, Sequencer, TapeRestarted},
+    SequencerState,
+};
+use crate::{
+    game::{
+        assets::{HandleMap, LdtkKey},
+        player::GoalReached,
+        spawn::level::{next_level_selection, NextLevel},
+    },
+    progress::{LevelRecord, Progress},
+    ui::prelude::*,
+};
+
+pub(super) fn plugin(app: &mut App) {
+    app.observe(clear_level);
+    app.add_systems(OnEnter(SequencerState::Cleared), enter_cleared);
+    app.add_systems(OnExit(SequencerState::Cleared), clear_results);
+
+    app.register_type::<ResultAction>();
+    app.add_systems(
+        Update,
+        handle_result_action.run_if(in_state(SequencerState::Cleared)),
+    );
+}
+
+/// How the level was cleared. The goal counts as reached until this is removed,
+/// as more fixed steps may run before the tape stops.
+#[derive(Resource, Debug, Clone, Copy)]
+pub struct LevelResults {
+    /// Play position when the goal was reached, in seconds.
+    pub time: f32,
+    /// Number of notes on the tape.
+    pub notes: usize,
+    /// Whether this beats the previous clears of the level.
+    pub new_best: bool,
+}
+
+#[derive(Component, Debug, Clone, Copy, PartialEq, Eq, Reflect)]
+#[reflect(Component)]
+enum ResultAction {
+    Retry,
+    NextLevel,
+}
+
+fn clear_level(
+    _trigger: Trigger<GoalReached>,
+    mut commands: Commands,
+    sequencer: Option<Res<Sequencer>>,
+    results: Option<Res<LevelResults>>,
+    seq_state: Option<Res<State<SequencerState>>>,
+    mut seq_state_next: ResMut<NextState<SequencerState>>,
+    level_iid: Query<&LevelIid>,
+    notes: Query<&Note>,
+    mut progress: ResMut<Progress>,
+) {
+    // The goal is only reached by playing the tape, not by seeking through it
+    let (Some(sequencer), Some(seq_state)) = (sequencer, seq_state) else {
+        return;
+    };
+    if *seq_state.get() != SequencerState::Playing || results.is_some() {
+        return;
+    }
+
+    let record = LevelRecord {
+        time: sequencer.play_pos(),
+        notes: sequencer.note_count(),
+    };
+    let new_best = match level_iid.iter().next() {
+        Some(iid) => {
+            // Only a new best changes the progress, and is worth saving
+            let new_best = progress.bypass_change_detection().record(
+                iid.get(),
+                record,
+                sequencer.capture(&notes),
+            );
+            if new_best {
+                progress.set_changed();
+            }
+            new_best
+        }
+        None => {
+            warn!("No level is spawned");
+            false
+        }
+    };
+
+    commands.insert_resource(LevelResults {
+        time: record.time,
+        notes: record.notes,
+        new_best,
+    });
+    seq_state_next.set(SequencerState::Cleared);
+}
+
+fn clear_results(mut commands: Commands) {
+    commands.remove_resource::<LevelResults>();
+}
+
+fn enter_cleared(
+    mut commands: Commands,
+    results: Res<LevelResults>,
+    level_selection: Res<LevelSelection>,
+    ldtk_handles: Res<HandleMap<LdtkKey>>,
+    projects: Res<Assets<LdtkProject>>,
+) {
+    let is_last_level = projects
+        .get(&ldtk_handles[&LdtkKey::Level])
+        .is_none_or(|project| next_level_selection(project, &level_selection).is_none());
+
+    commands
+        .ui_root()
+        .insert((
+            StateScoped(SequencerState::Cleared),
+            BackgroundColor(Color::srgba(0.0, 0.0, 0.0, 0.6)),
+            // above the sequencer UI
+            ZIndex::Global(10),
+        ))
+        .with_children(|children| {
+            children.header("Level Complete");
+            children.label(format!("Time: {:.2}s", results.time));
+            children.label(format!("Notes: {}", results.notes));
+            if results.new_best {
+                children.label("New best!");
+            }
+
+            children.button("Retry").insert(ResultAction::Retry);
+            // the last level goes back to the level select screen
+            let next_label = if is_last_level {
+                "Finish"
+            } else {
+                "Next Level"
+            };
+            children.button(next_label).insert(ResultAction::NextLevel);
+        });
+}
+
+fn handle_result_action(
+    mut commands: Commands,
+    mut button_query: InteractionQuery<&ResultAction>,
+    mut sequencer: ResMut<Sequencer>,
+    mut seq_state_next: ResMut<NextState<SequencerState>>,
+) {
+    for (interaction, action) in &mut button_query {
+        if matches!(interaction, Interaction::Pressed) {
+            match action {
+                // Rewind the tape and keep the notes to improve on them
+                ResultAction::Retry => {
+                    commands.trigger(TapeRestarted);
+                    sequencer.seek(0.0, &mut seq_state_next);
+                }
+                ResultAction::NextLevel => commands.trigger(NextLevel),
+            }
+        }
+    }
+}
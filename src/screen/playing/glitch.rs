@@ -0,0 +1,83 @@
+//! VHS glitch effect while the tape is seeking.
+
+use bevy::prelude::*;
+use bevy_video_glitch::VideoGlitchSettings;
+
+use super::{sequencer::Sequencer, SequencerState};
+use crate::settings::Settings;
+
+pub(super) fn plugin(app: &mut App) {
+    app.add_systems(Update, update_glitch);
+}
+
+// Seconds it takes for the glitch to fade in or out.
+const FADE_TIME: f32 = 0.3;
+
+// Seek speed (seconds of tape per second) at which the glitch is at full strength.
+const FULL_SPEED: f32 = 4.0;
+
+// Remaining seek distance (in seconds) at which the glitch is at full strength.
+const FULL_DISTANCE: f32 = 4.0;
+
+const MIN_INTENSITY: f32 = 0.05;
+const MAX_INTENSITY: f32 = 0.4;
+
+/// Fades the glitch in while seeking and out otherwise.
+/// The intensity grows with how fast and how far the tape is seeking.
+fn update_glitch(
+    mut commands: Commands,
+    // seeking is not affected by the playback speed
+    time: Res<Time<Real>>,
+    settings: Res<Settings>,
+    seq_state: Option<Res<State<SequencerState>>>,
+    sequencer: Option<Res<Sequencer>>,
+    mut cameras: Query<(Entity, Option<&mut VideoGlitchSettings>), With<Camera2d>>,
+    // 0.0 (off) to 1.0 (fully faded in)
+    mut fade: Local<f32>,
+    // intensity before fading, kept while fading out
+    mut strength: Local<f32>,
+    mut last_play_pos: Local<Option<f32>>,
+) {
+    let seeking = seq_state.is_some_and(|state| *state.get() == SequencerState::Seeking);
+
+    let play_pos = sequencer.as_ref().map(|seq| seq.play_pos());
+    if seeking {
+        if let (Some(sequencer), Some(play_pos), Some(last)) =
+            (&sequencer, play_pos, *last_play_pos)
+        {
+            let speed = (play_pos - last).abs() / time.delta_seconds().max(f32::EPSILON);
+            let speed = (speed / FULL_SPEED).min(1.0);
+            let distance = (sequencer.seek_distance() / FULL_DISTANCE).min(1.0);
+            *strength = MIN_INTENSITY + (MAX_INTENSITY - MIN_INTENSITY) * speed.max(distance);
+        }
+    }
+    *last_play_pos = play_pos;
+
+    let fade_step = time.delta_seconds() / FADE_TIME;
+    *fade = if seeking && settings.glitch_effect {
+        (*fade + fade_step).min(1.0)
+    } else {
+        (*fade - fade_step).max(0.0)
+    };
+
+    // smoothstep
+    let eased = *fade * *fade * (3.0 - 2.0 * *fade);
+    let intensity = *strength * eased;
+
+    for (camera, glitch) in &mut cameras {
+        match glitch {
+            // skip the post-processing pass entirely when there's nothing to show
+            Some(_) if intensity <= 0.0 => {
+                commands.entity(camera).remove::<VideoGlitchSettings>();
+            }
+            Some(mut glitch) => glitch.intensity = intensity,
+            None if intensity > 0.0 => {
+                commands.entity(camera).insert(VideoGlitchSettings {
+                    intensity,
+                    ..default()
+                });
+            }
+            None => {}
+        }
+    }
+}
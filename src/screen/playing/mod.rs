@@ -1,33 +1,37 @@
 //! The screen state for the main game loop.
 
-use bevy::{input::common_conditions::input_just_pressed, prelude::*};
+use bevy::prelude::*;
 
 use super::Screen;
-use crate::game::spawn::level::SpawnLevel;
+use crate::{game::spawn::level::SpawnLevel, settings::Settings};
 
+pub mod complete;
+mod glitch;
 pub mod sequencer;
 
 pub(super) fn plugin(app: &mut App) {
     // Sub-state of Screen::Playing
     app.add_sub_state::<SequencerState>();
+    app.enable_state_scoped_entities::<SequencerState>();
 
-    app.add_plugins(sequencer::plugin);
+    app.add_plugins((sequencer::plugin, glitch::plugin, complete::plugin));
 
     app.add_systems(OnEnter(Screen::Playing), enter_playing);
     app.add_systems(OnExit(Screen::Playing), exit_playing);
 
     app.add_systems(
         Update,
-        return_to_title_screen
-            .run_if(in_state(Screen::Playing).and_then(input_just_pressed(KeyCode::Escape))),
+        return_to_title_screen.run_if(
+            in_state(Screen::Playing)
+                .and_then(back_pressed)
+                // Escape cancels creating a note, or leaves keyboard editing instead
+                .and_then(not(resource_exists::<sequencer::NoteCreation>))
+                .and_then(not(resource_exists::<sequencer::EditCursor>)),
+        ),
     );
 }
 
-fn enter_playing(
-    mut commands: Commands,
-    mut proj: Query<&mut OrthographicProjection>,
-    mut seq_state: ResMut<NextState<SequencerState>>,
-) {
+fn enter_playing(mut commands: Commands, mut proj: Query<&mut OrthographicProjection>) {
     commands.trigger(SpawnLevel);
 
     let mut proj = proj.single_mut();
@@ -39,6 +43,10 @@ fn exit_playing(mut proj: Query<&mut OrthographicProjection>) {
     proj.scale = 1.0;
 }
 
+fn back_pressed(input: Res<ButtonInput<KeyCode>>, settings: Res<Settings>) -> bool {
+    input.just_pressed(settings.key_bindings.back)
+}
+
 fn return_to_title_screen(mut next_screen: ResMut<NextState<Screen>>) {
     next_screen.set(Screen::Title);
 }
@@ -47,9 +55,13 @@ fn return_to_title_screen(mut next_screen: ResMut<NextState<Screen>>) {
 // Now I don't think this is a good idea. A player needs to wait too long while just watching the sequencer play.
 // Instead, let's make the all replayed sequencers play at the same time.
 // No more "cycles", but OK. Not in time with the jam anyway.
+// Each loop of the tape now leaves a ghost robot that replays it along with the next ones
+// (see `game::ghost`).
+// Levels may also have several robots playing at the same time, each from its own tracks
+// (see `sequencer::robots`).
 
 // During seeking, use VideoGlitchSettings to simulate a VHS tape being rewound or fast-forwarded.
-// Maybe add tween to intensity of the glitch.
+// (see `glitch` module)
 
 #[derive(SubStates, Debug, Hash, PartialEq, Eq, Clone, Default)]
 #[source(Screen = Screen::Playing)]
@@ -57,11 +69,18 @@ pub enum SequencerState {
     /// Playing the sequencer
     // If the user added a note during play, go to Seeking state and unwind play time to where the note is added
     Playing,
-    /// Seeking animation is playing
+    /// Seeking animation is playing. The world is rewound to the seek target
+    /// (see `game::rewind`), then the sequencer goes back to `Playing`.
     // does not respond to UI interaction?
-    // Seeking to where?
     Seeking,
-    /// Sequencer stopped initially. Transitions to `Playing` state once the user adds the first note.
+    /// Paused with the transport controls (see `sequencer::transport`), or after stepping the tape.
+    /// The world is frozen, and playing resumes from exactly where it was.
+    Paused,
+    /// Sequencer stopped initially, or stopped and rewound to the start with the transport controls.
+    /// Transitions to `Playing` state once the user adds the first note, or plays the tape.
     #[default]
     Stopped,
+    /// The robot reached the goal. The world is frozen and the results are shown
+    /// (see `complete` module) until the player retries or moves on to the next level.
+    Cleared,
 }
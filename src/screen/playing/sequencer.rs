@@ -4,27 +4,45 @@
 // This module is responsible for the sequencer UI.
 
 // The sequencer UI has the seek bar. Receives play time via Resource.
-// When the seek bar is dragged, emits rollback/rollforward event.
+// When the seek bar is dragged, emits `Scrub` events (see `scrub`).
 // Triggers event for what note is being played.
 
-use bevy::color::palettes::css::WHITE;
+use avian2d::prelude::{Physics, PhysicsTime};
 use bevy::color::palettes::tailwind::*;
+use bevy::ecs::system::SystemParam;
 use bevy::prelude::*;
-use bevy::ui::{RelativeCursorPosition, Val::*};
+use bevy::ui::Val::*;
 
 use bevy_debug_text_overlay::OverlayPlugin;
+use bevy_tnua::TnuaUserControlsSystemSet;
 use sickle_ui_scaffold::drag_interaction::DragInteractionPlugin;
-use sickle_ui_scaffold::drop_interaction::{DropInteractionPlugin, DropZone};
-use sickle_ui_scaffold::flux_interaction::{FluxInteractionPlugin, TrackedInteraction};
+use sickle_ui_scaffold::drop_interaction::DropInteractionPlugin;
+use sickle_ui_scaffold::flux_interaction::FluxInteractionPlugin;
 
+use crate::game::spawn::level::NextLevel;
 use crate::screen::Screen;
+use crate::settings::{modifier_pressed, Settings};
 use crate::ui::prelude::*;
 
+mod history;
+mod keyboard;
 mod notes;
-
-pub use notes::{Note, NoteKind};
-
-use super::SequencerState;
+mod overlap;
+mod robots;
+mod scrub;
+mod selection;
+pub mod sequence;
+mod speed;
+mod timeline;
+mod transport;
+
+pub use keyboard::EditCursor;
+pub use notes::{Note, NoteCreation, NoteKind};
+pub use scrub::Scrub;
+use sequence::Sequence;
+use timeline::Timeline;
+
+use super::{complete::LevelResults, SequencerState};
 
 pub(super) fn plugin(app: &mut App) {
     app.add_plugins((
@@ -42,32 +60,186 @@ pub(super) fn plugin(app: &mut App) {
 
     app.add_systems(Update, update_seek_bar.run_if(in_state(Screen::Playing)));
 
+    // Notes are played in the fixed timestep, in lockstep with physics
     app.init_resource::<PlayingNotes>();
+    app.add_systems(
+        FixedUpdate,
+        (advance_play_pos, play_note)
+            .chain()
+            // the tape stops at the goal, though the state only changes on the next frame
+            .run_if(
+                in_state(SequencerState::Playing).and_then(not(resource_exists::<LevelResults>)),
+            )
+            .before(TnuaUserControlsSystemSet),
+    );
+
+    app.add_systems(OnExit(SequencerState::Playing), stop_pausing);
+    app.add_systems(OnEnter(SequencerState::Seeking), clear_playing_notes);
     app.add_systems(
         Update,
-        (advance_play_pos, play_note).run_if(in_state(SequencerState::Playing)),
+        advance_seek.run_if(
+            in_state(SequencerState::Seeking).and_then(not(resource_exists::<scrub::Scrubbing>)),
+        ),
     );
 
-    app.add_plugins(notes::plugin);
+    app.observe(reset_sequencer);
+
+    app.add_plugins((
+        history::plugin,
+        keyboard::plugin,
+        notes::plugin,
+        overlap::plugin,
+        robots::plugin,
+        scrub::plugin,
+        selection::plugin,
+        sequence::plugin,
+        speed::plugin,
+        timeline::plugin,
+        transport::plugin,
+    ));
 }
 
 #[derive(Resource)]
-struct Sequencer {
+pub struct Sequencer {
     id: Entity,
     notes: Vec<Entity>,
+    /// Length of the tape in seconds, which depends on the level.
+    length: f32,
     /// Play position in seconds.
     play_pos: f32,
+    /// Where the play position is heading to in `SequencerState::Seeking`.
+    seek_target: f32,
+    /// The state the sequencer goes to once the seek target is reached.
+    after_seek: SequencerState,
+    /// Where to pause while playing, when stepping the tape forward.
+    pause_at: Option<f32>,
+    /// Number of robots in the level, each with its own set of tracks.
+    robots: usize,
+    /// Whether the tape has played to its end and is about to be rewound,
+    /// so that [`LoopCompleted`] is only triggered once per loop.
+    looping: bool,
+    /// Whether the tape has reached `pause_at` and is about to be paused,
+    /// so that it stays there for the fixed steps left before the state changes.
+    pausing: bool,
 }
 
-#[derive(Component)]
-struct Track;
+impl Sequencer {
+    /// Play position in seconds.
+    pub fn play_pos(&self) -> f32 {
+        self.play_pos
+    }
+
+    /// Moves the play position back to `time`, where the world has been rewound to once seeked.
+    /// A tape seeked to be paused plays on from there up to where it was seeked,
+    /// so that the world gets there the same way as while playing.
+    pub fn resume_from(&mut self, time: f32, next_state: &mut NextState<SequencerState>) {
+        if self.after_seek == SequencerState::Paused && time < self.play_pos {
+            self.pause_at = Some(self.play_pos);
+            next_state.set(SequencerState::Playing);
+        }
+        self.play_pos = time;
+    }
+
+    /// How far the play position is from the seek target, in seconds.
+    pub fn seek_distance(&self) -> f32 {
+        (self.seek_target - self.play_pos).abs()
+    }
+
+    /// Number of notes on the tape.
+    pub fn note_count(&self) -> usize {
+        self.notes.len()
+    }
+
+    /// The latest position in seconds where a note `width` seconds long still fits on the tape.
+    pub(super) fn last_note_pos(&self, width: f32) -> f32 {
+        (self.length - width).max(0.0)
+    }
+
+    /// Starts seeking the tape to `target` seconds.
+    /// The world is rewound along the way and playing resumes from there.
+    pub(super) fn seek(&mut self, target: f32, next_state: &mut NextState<SequencerState>) {
+        self.seek_and(target, SequencerState::Playing, next_state);
+    }
+
+    /// Starts seeking the tape to `target` seconds, and goes to `then` once there.
+    fn seek_and(
+        &mut self,
+        target: f32,
+        then: SequencerState,
+        next_state: &mut NextState<SequencerState>,
+    ) {
+        self.seek_target = target.clamp(0.0, self.length);
+        self.after_seek = then;
+        self.pause_at = None;
+        next_state.set(SequencerState::Seeking);
+    }
+
+    /// Rewinds to just before `time` if the tape has already played past it,
+    /// so that an edit of the notes at `time` is observed from a consistent world state.
+    fn rewind_to_edit(
+        &mut self,
+        time: f32,
+        state: &SequencerState,
+        next_state: &mut NextState<SequencerState>,
+    ) {
+        let target = (time - EDIT_REWIND_MARGIN).max(0.0);
+        match state {
+            SequencerState::Playing if target < self.play_pos => self.seek(target, next_state),
+            // play the edit up to where the tape was paused
+            SequencerState::Paused if target < self.play_pos => {
+                let paused_at = self.play_pos;
+                self.seek(target, next_state);
+                self.pause_at = Some(paused_at);
+            }
+            // already seeking; just make sure we seek far enough
+            SequencerState::Seeking if target < self.seek_target => self.seek_target = target,
+            _ => {}
+        }
+    }
+}
+
+// How far before an edited note the tape is rewound, in seconds.
+const EDIT_REWIND_MARGIN: f32 = 0.1;
+
+/// A sequencer track. Notes placed on this track are of `kind`, and played by the robot `robot`.
+#[derive(Component, Clone, Copy)]
+struct Track {
+    kind: NoteKind,
+    robot: usize,
+}
 
 const TRACK_WIDTH: f32 = 500.0;
 
+/// Snaps note positions to the grid drawn on the tracks.
+#[derive(SystemParam)]
+struct Snapping<'w> {
+    settings: Res<'w, Settings>,
+    keys: Res<'w, ButtonInput<KeyCode>>,
+}
+
+impl Snapping<'_> {
+    /// Moves `time`, in seconds, to the nearest grid line, unless the bypass key is held.
+    fn snap(&self, time: f32) -> f32 {
+        if modifier_pressed(&self.keys, self.settings.key_bindings.bypass_snap) {
+            return time;
+        }
+        let step = grid_step(&self.settings);
+        (time / step).round() * step
+    }
+}
+
+/// Seconds between grid lines.
+// The grid is as fine as on a tape of the default length, whatever the length of the level.
+fn grid_step(settings: &Settings) -> f32 {
+    DEFAULT_LENGTH / settings.grid_divisions.max(1) as f32
+}
+
 #[derive(Component)]
 struct SeekBar;
 
-fn enter_playing(mut commands: Commands) {
+const SEEK_BAR_WIDTH: f32 = 5.0;
+
+fn enter_playing(mut commands: Commands, settings: Res<Settings>) {
     let mut seq_id = None;
     commands
         .ui_root_with_style(|style| Style {
@@ -77,6 +249,22 @@ fn enter_playing(mut commands: Commands) {
         .insert(StateScoped(Screen::Playing))
         .with_children(|children| {
             children.label("Sequencer");
+            children
+                .spawn((
+                    Name::new("Toolbar"),
+                    NodeBundle {
+                        style: Style {
+                            column_gap: Px(30.0),
+                            ..default()
+                        },
+                        ..default()
+                    },
+                ))
+                .with_children(|children| {
+                    history::spawn_history_buttons(children);
+                    transport::spawn_transport_buttons(children);
+                    speed::spawn_speed_button(children, &settings);
+                });
 
             seq_id = children
                 .spawn((
@@ -94,53 +282,30 @@ fn enter_playing(mut commands: Commands) {
                     },
                 ))
                 .with_children(|children| {
-                    children.spawn((
-                        Name::new("Seek Bar"),
-                        NodeBundle {
-                            style: Style {
-                                position_type: PositionType::Absolute,
-                                height: Percent(100.0),
-                                width: Px(5.0),
-                                ..default()
-                            },
-                            z_index: ZIndex::Local(999),
-                            background_color: BackgroundColor(BLUE_50.into()),
-                            ..default()
-                        },
-                        SeekBar,
-                    ));
-                    // TODO: add seek bar handle
-
-                    for s in ["Jump", "Attack", "Wait", "Backward"] {
-                        children.spawn(TextBundle::from_section(
-                            s,
-                            TextStyle {
-                                font_size: 24.0,
-                                color: WHITE.into(),
-                                ..default()
-                            },
-                        ));
-
-                        children.spawn((
-                            Name::new(format!("Track {}", s)),
-                            Track,
+                    children
+                        .spawn((
+                            Name::new("Seek Bar"),
                             NodeBundle {
                                 style: Style {
-                                    width: Px(TRACK_WIDTH),
-                                    height: Px(50.0),
-                                    border: UiRect::all(Px(3.0)),
-                                    overflow: Overflow::clip(),
+                                    position_type: PositionType::Absolute,
+                                    height: Percent(100.0),
+                                    width: Px(SEEK_BAR_WIDTH),
                                     ..default()
                                 },
-                                border_color: BLUE_200.into(),
+                                z_index: ZIndex::Local(999),
+                                background_color: BackgroundColor(BLUE_50.into()),
                                 ..default()
                             },
-                            Interaction::None,
-                            TrackedInteraction::default(),
-                            DropZone::default(),
-                            RelativeCursorPosition::default(),
-                        ));
-                    }
+                            SeekBar,
+                        ))
+                        .with_children(|children| {
+                            scrub::spawn_seek_handle(children, SEEK_BAR_WIDTH)
+                        });
+
+                    timeline::spawn_ruler(children);
+
+                    // more are added once the level is spawned, if it has more robots
+                    robots::spawn_robot_tracks(children, 0, 1);
                 })
                 .id()
                 .into();
@@ -149,16 +314,47 @@ fn enter_playing(mut commands: Commands) {
     commands.insert_resource(Sequencer {
         id: seq_id.unwrap(),
         notes: vec![],
+        // set once the level is spawned
+        length: DEFAULT_LENGTH,
         play_pos: 0.0,
+        seek_target: 0.0,
+        after_seek: SequencerState::Playing,
+        pause_at: None,
+        robots: 1,
+        looping: false,
+        pausing: false,
     });
+    commands.insert_resource(Timeline::new(DEFAULT_LENGTH));
 }
 
 fn exit_playing(mut commands: Commands) {
     commands.remove_resource::<Sequencer>();
+    commands.remove_resource::<Timeline>();
 }
 
-// What the play position, in seconds, will be if we seek fully from the left to the right.
-const TRACK_WIDTH_TIME: f32 = 8.0;
+// A new level starts with an empty, stopped tape
+fn reset_sequencer(
+    _trigger: Trigger<NextLevel>,
+    mut commands: Commands,
+    sequencer: Option<ResMut<Sequencer>>,
+    mut played_notes: ResMut<PlayingNotes>,
+    mut seq_state_next: ResMut<NextState<SequencerState>>,
+) {
+    let Some(mut sequencer) = sequencer else {
+        return;
+    };
+    for id in sequencer.notes.drain(..) {
+        commands.entity(id).despawn_recursive();
+    }
+    sequencer.play_pos = 0.0;
+    sequencer.seek_target = 0.0;
+    sequencer.pause_at = None;
+    played_notes.0.clear();
+    seq_state_next.set(SequencerState::Stopped);
+}
+
+// Length of the tape in seconds, for levels that don't set their own.
+const DEFAULT_LENGTH: f32 = 8.0;
 
 fn get_clipped_rect(node: &Node, gt: &GlobalTransform, clip: Option<&CalculatedClip>) -> Rect {
     let rect = node.logical_rect(gt);
@@ -171,6 +367,7 @@ fn update_seek_bar(
     tracks: Query<Entity, With<Track>>,
     mut seek_bar: Query<&mut Style, With<SeekBar>>,
     sequencer: Res<Sequencer>,
+    timeline: Res<Timeline>,
 ) {
     // UI rect is not updated in first update, so we run this every frame
     // technically we can save rects first and only update when sequencer is changed
@@ -208,26 +405,113 @@ fn update_seek_bar(
         debug!("Seek bar not found");
         return;
     };
-    style.left = Px(sequencer.play_pos / TRACK_WIDTH_TIME * TRACK_WIDTH + rel_track_x_min);
+    style.left = Px(timeline.to_px(sequencer.play_pos) + rel_track_x_min);
+    // hidden while the play position is scrolled out of view
+    style.display = if timeline.shows(sequencer.play_pos) {
+        Display::Flex
+    } else {
+        Display::None
+    };
 }
 
 fn advance_play_pos(
+    mut commands: Commands,
     time: Res<Time>,
     mut sequencer: ResMut<Sequencer>,
+    notes: Query<&Note>,
+    mut physics_time: ResMut<Time<Physics>>,
     mut playing_state: ResMut<NextState<SequencerState>>,
 ) {
+    // more fixed steps may run before the pause starts
+    if sequencer.pausing {
+        return;
+    }
+
     let delta = time.delta_seconds();
     sequencer.play_pos += delta;
 
-    if sequencer.play_pos > TRACK_WIDTH_TIME {
-        sequencer.play_pos = 0.0;
-        playing_state.set(SequencerState::Seeking);
+    if let Some(pause_at) = sequencer
+        .pause_at
+        .filter(|&pause_at| sequencer.play_pos >= pause_at)
+    {
+        sequencer.play_pos = pause_at;
+        sequencer.pause_at = None;
+        sequencer.pausing = true;
+        // the world stops right there too
+        physics_time.pause();
+        playing_state.set(SequencerState::Paused);
+    } else if sequencer.play_pos > sequencer.length {
+        sequencer.play_pos = sequencer.length;
+        // more fixed steps may run before the seek starts
+        if !sequencer.looping {
+            sequencer.looping = true;
+            commands.trigger(LoopCompleted(sequencer.capture(&notes)));
+            sequencer.seek(0.0, &mut playing_state);
+        }
+    } else {
+        sequencer.looping = false;
+    }
+}
+
+// Whatever the tape goes to instead of the pause, `game::rewind` pauses physics as it needs
+fn stop_pausing(mut sequencer: ResMut<Sequencer>, mut physics_time: ResMut<Time<Physics>>) {
+    if sequencer.pausing {
+        sequencer.pausing = false;
+        physics_time.unpause();
+    }
+}
+
+// Seconds of tape seeked per second, whatever the playback speed.
+const SEEK_SPEED: f32 = 4.0;
+
+// Moves the play position towards the seek target, and resumes playing once reached,
+// or goes to whichever state the seek was started for.
+// The world follows the play position in `game::rewind`.
+fn advance_seek(
+    time: Res<Time<Real>>,
+    mut sequencer: ResMut<Sequencer>,
+    mut playing_state: ResMut<NextState<SequencerState>>,
+) {
+    let step = SEEK_SPEED * time.delta_seconds();
+    let diff = sequencer.seek_target - sequencer.play_pos;
+
+    if diff.abs() <= step {
+        sequencer.play_pos = sequencer.seek_target;
+        playing_state.set(sequencer.after_seek.clone());
+    } else {
+        sequencer.play_pos += step.copysign(diff);
     }
 }
 
+/// Triggered when the tape has played to its end, with the notes it played,
+/// before it is rewound to play again.
+#[derive(Event, Debug)]
+pub struct LoopCompleted(pub Sequence);
+
+/// Triggered when the tape is stopped or played again from the start on purpose,
+/// rather than looping, so that what the earlier loops left behind is cleared.
+#[derive(Event, Debug)]
+pub struct TapeRestarted;
+
 #[derive(Resource, Debug, Default)]
 pub struct PlayingNotes(pub Vec<Note>);
 
+impl PlayingNotes {
+    /// The notes being played by the robot `robot`.
+    pub fn of(&self, robot: usize) -> Vec<Note> {
+        self.0
+            .iter()
+            .filter(|note| note.robot == robot)
+            .copied()
+            .collect()
+    }
+}
+
+// Notes are not played during seeking; don't keep acting on the last ones
+fn clear_playing_notes(mut played_notes: ResMut<PlayingNotes>) {
+    played_notes.0.clear();
+}
+
 fn play_note(
     sequencer: Res<Sequencer>,
     notes: Query<&Note>,
@@ -242,8 +526,7 @@ fn play_note(
 
     for &id in &sequencer.notes {
         if let Ok(note) = notes.get(id) {
-            if play_pos > note.pos && play_pos < note.pos + note.width {
-                // TODO: don't play newly added note under the seek bar midway
+            if note.is_playing_at(play_pos) {
                 playing.push(*note);
             }
         }
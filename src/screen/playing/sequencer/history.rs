@@ -0,0 +1,309 @@
+//! Undo and redo of the edits of notes in the sequencer.
+//!
+//! Every edit is recorded as the states of the notes it changed, before and after.
+//! Undoing an edit puts the notes back, and rewinds the tape to where they changed.
+
+use bevy::prelude::*;
+use bevy::ui::Val::*;
+
+use crate::game::spawn::level::NextLevel;
+use crate::screen::{playing::SequencerState, Screen};
+use crate::ui::prelude::*;
+
+use super::sequence::LoadSequence;
+use super::{Note, NoteCreation, Sequencer, Timeline, Track};
+
+pub(super) fn plugin(app: &mut App) {
+    app.add_systems(OnEnter(Screen::Playing), init_history);
+    app.add_systems(OnExit(Screen::Playing), remove_history);
+    app.observe(clear_history_on_load)
+        .observe(clear_history_on_next_level);
+
+    app.register_type::<HistoryAction>();
+    app.add_systems(
+        Update,
+        (history_keys, handle_history_action).run_if(
+            in_state(Screen::Playing)
+                .and_then(resource_exists::<EditHistory>)
+                // the notes are not edited while the level results are shown
+                .and_then(not(in_state(SequencerState::Cleared)))
+                .and_then(not(resource_exists::<NoteCreation>)),
+        ),
+    );
+}
+
+/// Where a note is.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub(super) struct NoteState {
+    pub track: Entity,
+    pub pos: f32,
+    pub width: f32,
+}
+
+impl NoteState {
+    pub(super) fn new(track: Entity, note: &Note) -> Self {
+        Self {
+            track,
+            pos: note.pos,
+            width: note.width,
+        }
+    }
+}
+
+/// A note before and after an edit. `None` if it didn't exist.
+#[derive(Clone, Copy, Debug)]
+pub(super) struct NoteChange {
+    note: Entity,
+    before: Option<NoteState>,
+    after: Option<NoteState>,
+}
+
+/// The notes changed by a single edit.
+#[derive(Default, Debug)]
+pub(super) struct Edit(Vec<NoteChange>);
+
+impl Edit {
+    /// Records a change of `note`. Changes of the same note are merged.
+    pub(super) fn record(
+        &mut self,
+        note: Entity,
+        before: Option<NoteState>,
+        after: Option<NoteState>,
+    ) {
+        match self.0.iter_mut().find(|change| change.note == note) {
+            Some(change) => change.after = after,
+            None => self.0.push(NoteChange {
+                note,
+                before,
+                after,
+            }),
+        }
+    }
+}
+
+/// Edits that can be undone, and edits undone that can be redone.
+#[derive(Resource, Default, Debug)]
+pub(super) struct EditHistory {
+    undo: Vec<Edit>,
+    redo: Vec<Edit>,
+}
+
+// Older edits are forgotten.
+const MAX_HISTORY: usize = 100;
+
+impl EditHistory {
+    pub(super) fn push(&mut self, edit: Edit) {
+        if edit.0.is_empty() {
+            return;
+        }
+        self.undo.push(edit);
+        if self.undo.len() > MAX_HISTORY {
+            self.undo.remove(0);
+        }
+        self.redo.clear();
+    }
+
+    /// A note removed by an edit is spawned again as another entity when the edit is undone.
+    fn replace_note(&mut self, old: Entity, new: Entity) {
+        for edit in self.undo.iter_mut().chain(&mut self.redo) {
+            for change in &mut edit.0 {
+                if change.note == old {
+                    change.note = new;
+                }
+            }
+        }
+    }
+
+    fn clear(&mut self) {
+        self.undo.clear();
+        self.redo.clear();
+    }
+}
+
+fn init_history(mut commands: Commands) {
+    commands.init_resource::<EditHistory>();
+}
+
+fn remove_history(mut commands: Commands) {
+    commands.remove_resource::<EditHistory>();
+}
+
+// The loaded notes are not edits
+fn clear_history_on_load(_trigger: Trigger<LoadSequence>, history: Option<ResMut<EditHistory>>) {
+    if let Some(mut history) = history {
+        history.clear();
+    }
+}
+
+fn clear_history_on_next_level(_trigger: Trigger<NextLevel>, history: Option<ResMut<EditHistory>>) {
+    if let Some(mut history) = history {
+        history.clear();
+    }
+}
+
+#[derive(Component, Debug, Clone, Copy, PartialEq, Eq, Reflect)]
+#[reflect(Component)]
+pub(super) enum HistoryAction {
+    Undo,
+    Redo,
+}
+
+fn history_keys(
+    keys: Res<ButtonInput<KeyCode>>,
+    mut commands: Commands,
+    mut history: ResMut<EditHistory>,
+    mut sequencer: ResMut<Sequencer>,
+    mut notes: Query<(&mut Note, &mut Style, &Parent)>,
+    tracks: Query<&Track>,
+    timeline: Res<Timeline>,
+    seq_state: Res<State<SequencerState>>,
+    mut seq_state_next: ResMut<NextState<SequencerState>>,
+) {
+    let ctrl = keys.any_pressed([KeyCode::ControlLeft, KeyCode::ControlRight]);
+    let shift = keys.any_pressed([KeyCode::ShiftLeft, KeyCode::ShiftRight]);
+    if !(ctrl && keys.just_pressed(KeyCode::KeyZ)) {
+        return;
+    }
+    let action = if shift {
+        HistoryAction::Redo
+    } else {
+        HistoryAction::Undo
+    };
+
+    apply_history_action(
+        action,
+        &mut commands,
+        &mut history,
+        &mut sequencer,
+        &mut notes,
+        &tracks,
+        &timeline,
+        &seq_state,
+        &mut seq_state_next,
+    );
+}
+
+fn handle_history_action(
+    mut button_query: InteractionQuery<&HistoryAction>,
+    mut commands: Commands,
+    mut history: ResMut<EditHistory>,
+    mut sequencer: ResMut<Sequencer>,
+    mut notes: Query<(&mut Note, &mut Style, &Parent)>,
+    tracks: Query<&Track>,
+    timeline: Res<Timeline>,
+    seq_state: Res<State<SequencerState>>,
+    mut seq_state_next: ResMut<NextState<SequencerState>>,
+) {
+    for (interaction, &action) in &mut button_query {
+        if matches!(interaction, Interaction::Pressed) {
+            apply_history_action(
+                action,
+                &mut commands,
+                &mut history,
+                &mut sequencer,
+                &mut notes,
+                &tracks,
+                &timeline,
+                &seq_state,
+                &mut seq_state_next,
+            );
+        }
+    }
+}
+
+/// Undoes the last edit, or redoes the last undone edit.
+fn apply_history_action(
+    action: HistoryAction,
+    commands: &mut Commands,
+    history: &mut EditHistory,
+    sequencer: &mut Sequencer,
+    notes: &mut Query<(&mut Note, &mut Style, &Parent)>,
+    tracks: &Query<&Track>,
+    timeline: &Timeline,
+    seq_state: &State<SequencerState>,
+    seq_state_next: &mut NextState<SequencerState>,
+) {
+    let edit = match action {
+        HistoryAction::Undo => history.undo.pop(),
+        HistoryAction::Redo => history.redo.pop(),
+    };
+    let Some(mut edit) = edit else {
+        return;
+    };
+
+    let mut changed_at = f32::INFINITY;
+    let mut replaced = vec![];
+    for change in &mut edit.0 {
+        let (from, to) = match action {
+            HistoryAction::Undo => (change.after, change.before),
+            HistoryAction::Redo => (change.before, change.after),
+        };
+        for state in from.iter().chain(&to) {
+            changed_at = changed_at.min(state.pos);
+        }
+
+        match (notes.get_mut(change.note), to) {
+            (Ok((mut note, mut style, parent)), Some(to)) => {
+                note.pos = to.pos;
+                note.width = to.width;
+                timeline.place(&mut style, to.pos, to.width);
+                if parent.get() != to.track {
+                    commands.entity(change.note).set_parent(to.track);
+                }
+            }
+            (Ok(_), None) => {
+                commands.entity(change.note).despawn_recursive();
+                sequencer.notes.retain(|&id| id != change.note);
+            }
+            (Err(_), Some(to)) => {
+                let Ok(track) = tracks.get(to.track) else {
+                    continue;
+                };
+                let mut id = None;
+                commands.entity(to.track).with_children(|children| {
+                    id = Some(Note::spawn(children, *track, to.pos, to.width).id());
+                });
+                let id = id.unwrap();
+                sequencer.notes.push(id);
+                replaced.push((change.note, id));
+                change.note = id;
+            }
+            (Err(_), None) => {}
+        }
+    }
+
+    match action {
+        HistoryAction::Undo => history.redo.push(edit),
+        HistoryAction::Redo => history.undo.push(edit),
+    }
+    for (old, new) in replaced {
+        history.replace_note(old, new);
+    }
+
+    if changed_at.is_finite() {
+        if *seq_state.get() == SequencerState::Stopped {
+            seq_state_next.set(SequencerState::Playing);
+        } else {
+            sequencer.rewind_to_edit(changed_at, seq_state.get(), seq_state_next);
+        }
+    }
+}
+
+/// Spawns the undo and redo buttons.
+pub(super) fn spawn_history_buttons(spawner: &mut ChildBuilder) {
+    spawner
+        .spawn((
+            Name::new("Edit History"),
+            NodeBundle {
+                style: Style {
+                    column_gap: Px(10.0),
+                    ..default()
+                },
+                ..default()
+            },
+        ))
+        .with_children(|children| {
+            children.small_button("Undo").insert(HistoryAction::Undo);
+            children.small_button("Redo").insert(HistoryAction::Redo);
+        });
+}
@@ -0,0 +1,303 @@
+//! Editing the sequencer with the keyboard alone.
+//!
+//! While keyboard editing is on, an edit cursor is shown on one of the tracks.
+//! It moves along the tape in grid steps and between tracks, and notes are inserted,
+//! deleted, nudged and resized where it is. See [`KeyBindings`](crate::settings::KeyBindings).
+
+use bevy::color::palettes::tailwind::ORANGE_400;
+use bevy::prelude::*;
+use bevy::ui::Val::*;
+
+use crate::screen::{playing::SequencerState, Screen};
+use crate::settings::{modifier_pressed, Settings};
+
+use super::history::{Edit, EditHistory, NoteState};
+use super::notes::{make_room, shifted_track, track_index, NoteQuery};
+use super::overlap::{RejectedEdit, EPSILON};
+use super::{grid_step, Note, NoteCreation, Sequencer, Timeline, Track};
+
+pub(super) fn plugin(app: &mut App) {
+    app.add_systems(OnExit(Screen::Playing), remove_edit_cursor);
+    app.add_systems(
+        Update,
+        (
+            toggle_keyboard_editing,
+            (move_edit_cursor, edit_at_cursor, show_edit_cursor)
+                .chain()
+                .run_if(resource_exists::<EditCursor>),
+        )
+            .chain()
+            .run_if(
+                in_state(Screen::Playing)
+                    .and_then(not(in_state(SequencerState::Cleared)))
+                    .and_then(not(resource_exists::<NoteCreation>)),
+            ),
+    );
+}
+
+/// Where notes are edited with the keyboard. Present while keyboard editing is on.
+#[derive(Resource, Debug)]
+pub struct EditCursor {
+    track: Entity,
+    /// Position on the tape in seconds.
+    time: f32,
+    node: Entity,
+}
+
+impl EditCursor {
+    /// The track the cursor is on.
+    pub(super) fn track(&self) -> Entity {
+        self.track
+    }
+}
+
+// Width of the edit cursor in pixels.
+const CURSOR_WIDTH: f32 = 3.0;
+
+fn remove_edit_cursor(mut commands: Commands) {
+    commands.remove_resource::<EditCursor>();
+}
+
+fn toggle_keyboard_editing(
+    mut commands: Commands,
+    keys: Res<ButtonInput<KeyCode>>,
+    settings: Res<Settings>,
+    cursor: Option<Res<EditCursor>>,
+    tracks: Query<(Entity, &Track)>,
+    sequencer: Res<Sequencer>,
+) {
+    let bindings = &settings.key_bindings;
+
+    if let Some(cursor) = cursor {
+        if keys.any_just_pressed([bindings.keyboard_editing, bindings.back]) {
+            commands.entity(cursor.node).despawn_recursive();
+            commands.remove_resource::<EditCursor>();
+        }
+        return;
+    }
+    if !keys.just_pressed(bindings.keyboard_editing) {
+        return;
+    }
+
+    // Start on the top track, at the grid line nearest to the play position
+    let Some((track, _)) = tracks
+        .iter()
+        .min_by_key(|&(track, _)| track_index(&tracks, track))
+    else {
+        return;
+    };
+    let step = grid_step(&settings);
+    let time = ((sequencer.play_pos / step).round() * step).min(sequencer.length);
+
+    let mut node = None;
+    commands.entity(track).with_children(|children| {
+        node = children
+            .spawn((
+                Name::new("Edit Cursor"),
+                NodeBundle {
+                    style: Style {
+                        position_type: PositionType::Absolute,
+                        width: Px(CURSOR_WIDTH),
+                        height: Percent(100.0),
+                        ..default()
+                    },
+                    background_color: ORANGE_400.into(),
+                    z_index: ZIndex::Local(10),
+                    ..default()
+                },
+            ))
+            .id()
+            .into();
+    });
+    commands.insert_resource(EditCursor {
+        track,
+        time,
+        node: node.unwrap(),
+    });
+}
+
+fn move_edit_cursor(
+    keys: Res<ButtonInput<KeyCode>>,
+    settings: Res<Settings>,
+    mut cursor: ResMut<EditCursor>,
+    tracks: Query<(Entity, &Track)>,
+    sequencer: Res<Sequencer>,
+    mut timeline: ResMut<Timeline>,
+) {
+    let bindings = &settings.key_bindings;
+    // the arrows nudge or resize the note instead
+    if modifier_pressed(&keys, bindings.nudge_note) || modifier_pressed(&keys, bindings.resize_note)
+    {
+        return;
+    }
+
+    let step = grid_step(&settings);
+    if keys.just_pressed(bindings.cursor_left) {
+        cursor.time = (cursor.time - step).max(0.0);
+    }
+    if keys.just_pressed(bindings.cursor_right) {
+        cursor.time = (cursor.time + step).min(sequencer.length);
+    }
+    if !timeline.shows(cursor.time) {
+        timeline.reveal(cursor.time, sequencer.length);
+    }
+
+    let offset = keys.just_pressed(bindings.track_down) as isize
+        - keys.just_pressed(bindings.track_up) as isize;
+    if offset != 0 {
+        if let Some(track) = shifted_track(&tracks, cursor.track, offset) {
+            cursor.track = track;
+        }
+    }
+}
+
+// Inserts, deletes, nudges or resizes a note at the edit cursor.
+fn edit_at_cursor(
+    mut commands: Commands,
+    keys: Res<ButtonInput<KeyCode>>,
+    settings: Res<Settings>,
+    mut cursor: ResMut<EditCursor>,
+    mut notes: NoteQuery,
+    tracks: Query<&Track>,
+    mut sequencer: ResMut<Sequencer>,
+    mut history: ResMut<EditHistory>,
+    timeline: Res<Timeline>,
+    seq_state: Res<State<SequencerState>>,
+    mut seq_state_next: ResMut<NextState<SequencerState>>,
+) {
+    let bindings = &settings.key_bindings;
+    let step = grid_step(&settings);
+    let track = cursor.track;
+
+    // The note under the edit cursor, not one just touching it
+    let focused = notes
+        .iter()
+        .find(|(_, note, _, parent)| {
+            parent.get() == track
+                && note.pos <= cursor.time + EPSILON
+                && cursor.time < note.pos + note.width - EPSILON
+        })
+        .map(|(entity, note, _, _)| (entity, *note));
+    let direction = if keys.just_pressed(bindings.cursor_left) {
+        -1.0
+    } else if keys.just_pressed(bindings.cursor_right) {
+        1.0
+    } else {
+        0.0
+    };
+
+    let mut edit = Edit::default();
+    let changed_at = if keys.just_pressed(bindings.insert_note) {
+        let Ok(&target) = tracks.get(track) else {
+            return;
+        };
+        let pos = cursor.time;
+        let width = Note::DEFAULT_WIDTH.min(sequencer.length - pos);
+        let state = NoteState { track, pos, width };
+        let resolved = (width >= Note::MIN_WIDTH)
+            .then(|| {
+                make_room(
+                    &mut commands,
+                    &mut sequencer,
+                    &timeline,
+                    &mut notes,
+                    &mut edit,
+                    settings.overlap_mode,
+                    &[(None, state)],
+                )
+            })
+            .flatten();
+        let Some(others_changed_at) = resolved else {
+            commands.entity(track).insert(RejectedEdit::bundle());
+            return;
+        };
+        commands.entity(track).with_children(|children| {
+            let id = Note::spawn(children, target, pos, width).id();
+            sequencer.notes.push(id);
+            edit.record(id, None, Some(state));
+        });
+        pos.min(others_changed_at)
+    } else if keys.just_pressed(bindings.delete_note) {
+        let Some((entity, note)) = focused else {
+            return;
+        };
+        commands.entity(entity).despawn_recursive();
+        sequencer.notes.retain(|&id| id != entity);
+        edit.record(entity, Some(NoteState::new(track, &note)), None);
+        note.pos
+    } else if direction != 0.0
+        && (modifier_pressed(&keys, bindings.nudge_note)
+            || modifier_pressed(&keys, bindings.resize_note))
+    {
+        let Some((entity, note)) = focused else {
+            return;
+        };
+        let resizing = modifier_pressed(&keys, bindings.resize_note);
+        let (pos, width) = if resizing {
+            let max_width = (sequencer.length - note.pos).max(Note::MIN_WIDTH);
+            let width = (note.width + direction * step).clamp(Note::MIN_WIDTH, max_width);
+            (note.pos, width)
+        } else {
+            let pos = (note.pos + direction * step).clamp(0.0, sequencer.last_note_pos(note.width));
+            (pos, note.width)
+        };
+        if pos == note.pos && width == note.width {
+            return;
+        }
+
+        let after = NoteState { track, pos, width };
+        let Some(others_changed_at) = make_room(
+            &mut commands,
+            &mut sequencer,
+            &timeline,
+            &mut notes,
+            &mut edit,
+            settings.overlap_mode,
+            &[(Some(entity), after)],
+        ) else {
+            commands.entity(entity).insert(RejectedEdit::bundle());
+            return;
+        };
+        let Ok((_, mut edited, mut style, _)) = notes.get_mut(entity) else {
+            return;
+        };
+        edited.pos = pos;
+        edited.width = width;
+        timeline.place(&mut style, pos, width);
+        edit.record(entity, Some(NoteState::new(track, &note)), Some(after));
+
+        // the edit cursor stays on a nudged note
+        cursor.time = (cursor.time + pos - note.pos).clamp(0.0, sequencer.length);
+
+        // The notes are unchanged before both the old and the new span
+        let changed_at = if resizing {
+            (pos + width).min(note.pos + note.width)
+        } else {
+            pos.min(note.pos)
+        };
+        changed_at.min(others_changed_at)
+    } else {
+        return;
+    };
+    history.push(edit);
+    // a stopped tape stays stopped, as with edits made with the mouse
+    sequencer.rewind_to_edit(changed_at, seq_state.get(), &mut seq_state_next);
+}
+
+fn show_edit_cursor(
+    mut commands: Commands,
+    cursor: Res<EditCursor>,
+    mut nodes: Query<(&mut Style, &Parent)>,
+    timeline: Res<Timeline>,
+) {
+    if !cursor.is_changed() && !timeline.is_changed() {
+        return;
+    }
+    let Ok((mut style, parent)) = nodes.get_mut(cursor.node) else {
+        return;
+    };
+    style.left = Px(timeline.to_px(cursor.time) - CURSOR_WIDTH * 0.5);
+    if parent.get() != cursor.track {
+        commands.entity(cursor.node).set_parent(cursor.track);
+    }
+}
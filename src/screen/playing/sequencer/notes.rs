@@ -1,21 +1,26 @@
 use bevy::color::palettes::css::{BLACK, WHITE};
+use bevy::color::palettes::tailwind::{GREEN_300, RED_300, YELLOW_200};
 use bevy::ecs::system::EntityCommands;
 use bevy::prelude::*;
-use bevy::ui::{RelativeCursorPosition, UiSystem, Val::*};
+use bevy::ui::{FocusPolicy, RelativeCursorPosition, UiSystem, Val::*};
 use bevy_debug_text_overlay::screen_print;
-use rand::seq;
+use serde::{Deserialize, Serialize};
 use sickle_ui_scaffold::prelude::{
     DragState, Draggable, DraggableUpdate, DropPhase, DropZone, Droppable, FluxInteraction,
     FluxInteractionUpdate, TrackedInteraction,
 };
-use std::ops::Deref;
+use std::iter;
 
 use crate::screen::playing::SequencerState;
+use crate::settings::{OverlapMode, Settings};
 use crate::ui::prelude::*;
 
 use crate::screen::Screen;
 
-use super::{Sequencer, Track, TRACK_WIDTH, TRACK_WIDTH_TIME};
+use super::history::{Edit, EditHistory, NoteState};
+use super::overlap::{self, RejectedEdit, EPSILON};
+use super::selection::{self, Selected};
+use super::{Sequencer, Snapping, Timeline, Track, TRACK_WIDTH};
 
 pub fn plugin(app: &mut App) {
     app.register_type::<NoteDragged>();
@@ -25,15 +30,21 @@ pub fn plugin(app: &mut App) {
     // Handles adding notes
     app.add_systems(
         Update,
-        track_interaction
+        (
+            track_interaction.run_if(not(in_state(SequencerState::Seeking))),
+            update_note_creation.run_if(resource_exists::<NoteCreation>),
+        )
+            .chain()
             .after(FluxInteractionUpdate)
-            .run_if(in_state(Screen::Playing).and_then(not(in_state(SequencerState::Seeking)))),
+            .after(DraggableUpdate)
+            .run_if(in_state(Screen::Playing)),
     );
 
     // Handles dragging notes
     app.add_systems(
         Update,
         (
+            note_resize,
             note_drag,
             (note_move_between_tracks, note_move_inactive).run_if(resource_exists::<NoteDragged>),
         )
@@ -42,23 +53,61 @@ pub fn plugin(app: &mut App) {
             .after(DraggableUpdate),
     );
 
-    // When a note is added, set its initial position (convert time to pixels)
+    // When a note is added, set its initial position (convert time to pixels).
+    // When a note is moved to another track, it takes the kind and robot of that track.
     app.add_systems(
         PostUpdate,
-        set_initial_note_pos
+        (set_initial_note_pos, sync_note_track)
             .run_if(in_state(Screen::Playing))
             .before(UiSystem::Layout),
     );
 }
 
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
 pub enum NoteKind {
+    /// Makes the robot jump.
     Jump,
+    /// Hits things in front of the robot.
+    Attack,
+    /// Halts the robot while the note plays.
+    Wait,
+    /// Reverses the walk direction while the note plays.
+    Backward,
+}
+
+impl NoteKind {
+    /// All kinds, in the order of tracks in the sequencer UI.
+    pub const ALL: [NoteKind; 4] = [
+        NoteKind::Jump,
+        NoteKind::Attack,
+        NoteKind::Wait,
+        NoteKind::Backward,
+    ];
+
+    pub fn label(self) -> &'static str {
+        match self {
+            NoteKind::Jump => "Jump",
+            NoteKind::Attack => "Attack",
+            NoteKind::Wait => "Wait",
+            NoteKind::Backward => "Backward",
+        }
+    }
+
+    fn color(self) -> Color {
+        match self {
+            NoteKind::Jump => WHITE.into(),
+            NoteKind::Attack => RED_300.into(),
+            NoteKind::Wait => YELLOW_200.into(),
+            NoteKind::Backward => GREEN_300.into(),
+        }
+    }
 }
 
 #[derive(Component, Clone, Copy, Debug)]
 pub struct Note {
     pub kind: NoteKind,
+    /// Index of the robot playing this note, that of its track.
+    pub robot: usize,
     /// Position of this note in seconds, which is the time when this note should be played.
     // this will be applied to Style::left on `Added<Note>`,
     // and during dragging in [`super::interaction`] module.
@@ -69,65 +118,182 @@ pub struct Note {
 
 impl Note {
     pub const DEFAULT_WIDTH: f32 = 1.0;
+    /// Notes can't be resized to be narrower than this, in seconds.
+    pub const MIN_WIDTH: f32 = 0.25;
 
-    pub fn spawn(spawner: &mut impl Spawn, pos: f32) -> EntityCommands {
-        spawner.spawn((
-            TextBundle::from_section(
-                "Drag me",
-                TextStyle {
-                    font_size: 24.0,
-                    color: BLACK.into(),
+    /// Whether this note is played when the play position is at `time` seconds.
+    pub fn is_playing_at(&self, time: f32) -> bool {
+        time > self.pos && time < self.pos + self.width
+    }
+
+    /// Spawns a note on `track`. `spawner` should be that track.
+    pub(super) fn spawn(
+        spawner: &mut impl Spawn,
+        track: Track,
+        pos: f32,
+        width: f32,
+    ) -> EntityCommands<'_> {
+        let Track { kind, robot } = track;
+        let mut note = spawner.spawn((
+            NodeBundle {
+                style: Style {
+                    height: Percent(100.0),
+                    position_type: PositionType::Absolute,
+                    align_items: AlignItems::Center,
+                    overflow: Overflow::clip(),
+                    // colored when selected
+                    border: UiRect::all(Px(2.0)),
                     ..default()
                 },
-            )
-            .with_style(Style {
-                width: Px(Note::DEFAULT_WIDTH * TRACK_WIDTH / TRACK_WIDTH_TIME),
-                height: Percent(100.0),
-                position_type: PositionType::Absolute,
-                align_content: AlignContent::Center,
+                background_color: kind.color().into(),
+                border_color: Color::NONE.into(),
                 ..default()
-            })
-            .with_background_color(WHITE.into()),
+            },
             Interaction::None,
             TrackedInteraction::default(),
             Draggable::default(),
             Droppable,
             RelativeCursorPosition::default(),
             Note {
-                // TODO
-                kind: NoteKind::Jump,
+                kind,
+                robot,
                 pos,
-                width: Note::DEFAULT_WIDTH,
+                width,
+            },
+        ));
+        note.with_children(|children| {
+            children.spawn(TextBundle::from_section(
+                "Drag me",
+                TextStyle {
+                    font_size: 24.0,
+                    color: BLACK.into(),
+                    ..default()
+                },
+            ));
+            for edge in [NoteEdge::Left, NoteEdge::Right] {
+                NoteHandle::spawn(children, edge);
+            }
+        });
+        note
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum NoteEdge {
+    Left,
+    Right,
+}
+
+/// Resizes the parent note when dragged.
+#[derive(Component, Clone, Copy, Debug)]
+pub(super) struct NoteHandle {
+    edge: NoteEdge,
+}
+
+impl NoteHandle {
+    // Width of the handle in pixels.
+    const WIDTH: f32 = 6.0;
+
+    fn spawn(spawner: &mut impl Spawn, edge: NoteEdge) -> EntityCommands<'_> {
+        let (left, right) = match edge {
+            NoteEdge::Left => (Px(0.0), Auto),
+            NoteEdge::Right => (Auto, Px(0.0)),
+        };
+        spawner.spawn((
+            Name::new("Note Handle"),
+            NodeBundle {
+                style: Style {
+                    width: Px(Self::WIDTH),
+                    height: Percent(100.0),
+                    position_type: PositionType::Absolute,
+                    left,
+                    right,
+                    ..default()
+                },
+                // so that pressing a handle doesn't drag the note itself
+                focus_policy: FocusPolicy::Block,
+                background_color: Color::NONE.into(),
+                ..default()
             },
+            InteractionPalette {
+                none: Color::NONE,
+                hovered: Color::srgba(1.0, 1.0, 1.0, 0.5),
+                pressed: Color::srgba(1.0, 1.0, 1.0, 0.8),
+            },
+            Interaction::None,
+            TrackedInteraction::default(),
+            Draggable::default(),
+            NoteHandle { edge },
         ))
     }
 }
 
 // Run this in PostUpdate, because only UI logic is dependent of the appearent position of notes
-fn set_initial_note_pos(mut added_notes: Query<(&Note, &mut Style), Added<Note>>) {
+fn set_initial_note_pos(
+    mut added_notes: Query<(&Note, &mut Style), Added<Note>>,
+    timeline: Res<Timeline>,
+) {
     for (note, mut style) in &mut added_notes {
-        // A note is a child of a track, so left = 0 when pos = 0
-        let ui_pos = note.pos / TRACK_WIDTH_TIME * TRACK_WIDTH;
-        style.left = Px(ui_pos);
+        // A note is a child of a track, so left = 0 at the left edge of the view
+        timeline.place(&mut style, note.pos, note.width);
     }
 }
 
-// If a track is pressed and no notes are hovered, spawn a note at the cursor position.
+// Run this in PostUpdate, after notes are reparented by `note_move_between_tracks` or `note_move_inactive`
+fn sync_note_track(
+    mut notes: Query<(&mut Note, &mut BackgroundColor, &Parent), Changed<Parent>>,
+    tracks: Query<&Track>,
+) {
+    for (mut note, mut background, parent) in &mut notes {
+        let Ok(track) = tracks.get(parent.get()) else {
+            continue;
+        };
+        if note.kind != track.kind {
+            note.kind = track.kind;
+            *background = track.kind.color().into();
+        }
+        if note.robot != track.robot {
+            note.robot = track.robot;
+        }
+    }
+}
+
+/// A note being created by pressing a track. Releasing the press without dragging
+/// creates a note centered at the cursor, and dragging creates a note spanning the drag.
+/// Escape or a right-click cancels it.
+#[derive(Resource, Debug)]
+pub struct NoteCreation {
+    track: Entity,
+    /// Where the track was pressed, in seconds.
+    start: f32,
+    /// Shows the span of the note while dragging.
+    preview: Option<Entity>,
+}
+
+#[derive(Component)]
+struct NotePreview;
+
+// If a track is pressed and no notes are hovered, start creating a note at the cursor position.
 fn track_interaction(
     mut commands: Commands,
-    notes: Query<&Interaction, With<Note>>,
+    notes: Query<&Interaction, Or<(With<Note>, With<NoteHandle>)>>,
     tracks: Query<
         (Entity, &Name, &FluxInteraction, &RelativeCursorPosition),
-        (With<Track>, Changed<FluxInteraction>),
+        Changed<FluxInteraction>,
     >,
-    mut sequencer: ResMut<Sequencer>,
-    mut seq_state: Res<State<SequencerState>>,
-    mut seq_state_next: ResMut<NextState<SequencerState>>,
+    creation: Option<Res<NoteCreation>>,
+    keys: Res<ButtonInput<KeyCode>>,
+    settings: Res<Settings>,
+    timeline: Res<Timeline>,
 ) {
     let any_notes_interacted = notes
         .iter()
         .any(|&interaction| interaction != Interaction::None);
-    if any_notes_interacted {
+    // pressing a track while extending the selection starts a rubber band instead
+    if any_notes_interacted
+        || creation.is_some()
+        || selection::is_extending(&keys, &settings.key_bindings)
+    {
         return;
     }
 
@@ -142,90 +308,612 @@ fn track_interaction(
                 return;
             };
 
-            commands.entity(track_id).with_children(|child| {
-                // cur_x is relative to the size of the track
-                // TODO: for a single click, spawn a note centered at the cursor,
-                // and for a drag, spawn a note with the width of the drag.
-                let pos_sec = cur_x * TRACK_WIDTH_TIME - Note::DEFAULT_WIDTH * 0.5;
-                let id = Note::spawn(child, pos_sec).id();
-                sequencer.notes.push(id);
+            // cur_x is relative to the size of the track
+            commands.insert_resource(NoteCreation {
+                track: track_id,
+                start: timeline.to_time(cur_x * TRACK_WIDTH),
+                preview: None,
+            });
+            return;
+        }
+    }
+}
+
+/// Position and width of a note spanning from `start` to `end` seconds, in either direction,
+/// on a tape `length` seconds long.
+fn drag_span(start: f32, end: f32, length: f32) -> (f32, f32) {
+    let width = (end - start).abs().max(Note::MIN_WIDTH);
+    let pos = start.min(end).clamp(0.0, (length - width).max(0.0));
+    (pos, width)
+}
+
+fn update_note_creation(
+    mut commands: Commands,
+    mut creation: ResMut<NoteCreation>,
+    tracks: Query<(&Track, &Draggable, &RelativeCursorPosition)>,
+    mut previews: Query<&mut Style, (With<NotePreview>, Without<Note>)>,
+    mut notes: NoteQuery,
+    mouse: Res<ButtonInput<MouseButton>>,
+    keys: Res<ButtonInput<KeyCode>>,
+    snapping: Snapping,
+    settings: Res<Settings>,
+    mut sequencer: ResMut<Sequencer>,
+    mut history: ResMut<EditHistory>,
+    timeline: Res<Timeline>,
+    seq_state: Res<State<SequencerState>>,
+    mut seq_state_next: ResMut<NextState<SequencerState>>,
+) {
+    let Ok((track, draggable, rel_cur_pos)) = tracks.get(creation.track) else {
+        commands.remove_resource::<NoteCreation>();
+        return;
+    };
+
+    let canceled = keys.just_pressed(settings.key_bindings.back)
+        || mouse.just_pressed(MouseButton::Right)
+        || draggable.state == DragState::DragCanceled;
+    let released = mouse.just_released(MouseButton::Left);
+    if canceled || released {
+        if let Some(preview) = creation.preview {
+            commands.entity(preview).despawn_recursive();
+        }
+        commands.remove_resource::<NoteCreation>();
+    }
+    if canceled {
+        return;
+    }
+
+    // the cursor may be outside of the track while dragging
+    let cur_x = rel_cur_pos
+        .normalized
+        .map_or(0.0, |pos| pos.x.clamp(0.0, 1.0));
+    let (pos, width) = drag_span(
+        snapping.snap(creation.start),
+        snapping.snap(timeline.to_time(cur_x * TRACK_WIDTH)),
+        sequencer.length,
+    );
+
+    if released {
+        let (pos, width) = if creation.preview.is_some() {
+            (pos, width)
+        } else {
+            // a click
+            let pos = snapping
+                .snap(creation.start - Note::DEFAULT_WIDTH * 0.5)
+                .clamp(0.0, sequencer.last_note_pos(Note::DEFAULT_WIDTH));
+            (pos, Note::DEFAULT_WIDTH)
+        };
+        let mut edit = Edit::default();
+        let Some(others_changed_at) = make_room(
+            &mut commands,
+            &mut sequencer,
+            &timeline,
+            &mut notes,
+            &mut edit,
+            settings.overlap_mode,
+            &[(
+                None,
+                NoteState {
+                    track: creation.track,
+                    pos,
+                    width,
+                },
+            )],
+        ) else {
+            commands
+                .entity(creation.track)
+                .insert(RejectedEdit::bundle());
+            return;
+        };
+        commands.entity(creation.track).with_children(|child| {
+            let id = Note::spawn(child, *track, pos, width).id();
+            sequencer.notes.push(id);
+            let after = NoteState {
+                track: creation.track,
+                pos,
+                width,
+            };
+            edit.record(id, None, Some(after));
+        });
+        history.push(edit);
+
+        if *seq_state.get() == SequencerState::Stopped {
+            seq_state_next.set(SequencerState::Playing);
+        } else {
+            sequencer.rewind_to_edit(
+                pos.min(others_changed_at),
+                seq_state.get(),
+                &mut seq_state_next,
+            );
+        }
+        return;
+    }
+
+    if !matches!(draggable.state, DragState::DragStart | DragState::Dragging) {
+        return;
+    }
+
+    let left = Px(timeline.to_px(pos));
+    let width = Px(timeline.duration_to_px(width));
+    match creation
+        .preview
+        .and_then(|preview| previews.get_mut(preview).ok())
+    {
+        Some(mut style) => {
+            style.left = left;
+            style.width = width;
+        }
+        None => {
+            let mut preview = None;
+            commands.entity(creation.track).with_children(|children| {
+                preview = children
+                    .spawn((
+                        Name::new("Note Preview"),
+                        NodeBundle {
+                            style: Style {
+                                left,
+                                width,
+                                height: Percent(100.0),
+                                position_type: PositionType::Absolute,
+                                ..default()
+                            },
+                            background_color: track.kind.color().with_alpha(0.5).into(),
+                            ..default()
+                        },
+                        NotePreview,
+                    ))
+                    .id()
+                    .into();
             });
+            creation.preview = preview;
+        }
+    }
+}
+
+/// Resizes a note while one of its handles is dragged, and applies it to
+/// [`Note::pos`] and [`Note::width`] when released.
+fn note_resize(
+    mut commands: Commands,
+    handles: Query<(&NoteHandle, &Draggable, &Parent), Changed<Draggable>>,
+    mut notes: NoteQuery,
+    snapping: Snapping,
+    settings: Res<Settings>,
+    mut sequencer: ResMut<Sequencer>,
+    mut history: ResMut<EditHistory>,
+    timeline: Res<Timeline>,
+    seq_state: Res<State<SequencerState>>,
+    mut seq_state_next: ResMut<NextState<SequencerState>>,
+) {
+    for (handle, draggable, parent) in &handles {
+        let entity = parent.get();
+        let Ok((_, note, mut style, track)) = notes.get_mut(entity) else {
+            continue;
+        };
+        // the note keeps its original span until the handle is released
+        let end = note.pos + note.width;
+
+        match draggable.state {
+            DragState::DragStart | DragState::Dragging => {
+                let (Some(origin), Some(position)) = (draggable.origin, draggable.position) else {
+                    continue;
+                };
+                let delta = timeline.px_to_duration(position.x - origin.x);
+                let (pos, width) = match handle.edge {
+                    NoteEdge::Left => {
+                        let pos = snapping
+                            .snap(note.pos + delta)
+                            .clamp(0.0, (end - Note::MIN_WIDTH).max(0.0));
+                        (pos, end - pos)
+                    }
+                    NoteEdge::Right => {
+                        let max_width = (sequencer.length - note.pos).max(Note::MIN_WIDTH);
+                        let width = (snapping.snap(end + delta) - note.pos)
+                            .clamp(Note::MIN_WIDTH, max_width);
+                        (note.pos, width)
+                    }
+                };
+                timeline.place(&mut style, pos, width);
+            }
+            DragState::DragEnd => {
+                let (Px(left), Px(width)) = (style.left, style.width) else {
+                    continue;
+                };
+                let pos = timeline.to_time(left);
+                let width = timeline.px_to_duration(width);
+                if (pos - note.pos).abs() < EPSILON && (width - note.width).abs() < EPSILON {
+                    continue;
+                }
+
+                // The notes are unchanged before both the old and the new span
+                let changed_at = match handle.edge {
+                    NoteEdge::Left => pos.min(note.pos),
+                    NoteEdge::Right => (pos + width).min(end),
+                };
+                let track = track.get();
+                let mut edit = Edit::default();
+                let resolved = make_room(
+                    &mut commands,
+                    &mut sequencer,
+                    &timeline,
+                    &mut notes,
+                    &mut edit,
+                    settings.overlap_mode,
+                    &[(Some(entity), NoteState { track, pos, width })],
+                );
+                let Ok((_, mut note, mut style, _)) = notes.get_mut(entity) else {
+                    continue;
+                };
+                match resolved {
+                    Some(others_changed_at) => {
+                        let before = NoteState::new(track, &note);
+                        note.pos = pos;
+                        note.width = width;
+                        edit.record(entity, Some(before), Some(NoteState::new(track, &note)));
+                        history.push(edit);
+                        sequencer.rewind_to_edit(
+                            changed_at.min(others_changed_at),
+                            seq_state.get(),
+                            &mut seq_state_next,
+                        );
+                    }
+                    None => {
+                        timeline.place(&mut style, note.pos, note.width);
+                        commands.entity(entity).insert(RejectedEdit::bundle());
+                    }
+                }
+            }
+            DragState::DragCanceled => {
+                timeline.place(&mut style, note.pos, note.width);
+            }
+            _ => {}
+        }
+    }
+}
+
+pub(super) type NoteQuery<'w, 's> = Query<
+    'w,
+    's,
+    (
+        Entity,
+        &'static mut Note,
+        &'static mut Style,
+        &'static Parent,
+    ),
+>;
+
+/// Makes room for notes placed at `placements` by changing the other notes on their tracks
+/// as set by [`Settings::overlap_mode`]. Each placement is of the note being moved or resized,
+/// or of `None` for a note about to be spawned. The changes are recorded in `edit`.
+/// Returns `None` if the edit is rejected, or else the earliest time other notes changed at.
+pub(super) fn make_room(
+    commands: &mut Commands,
+    sequencer: &mut Sequencer,
+    timeline: &Timeline,
+    notes: &mut NoteQuery,
+    edit: &mut Edit,
+    mode: OverlapMode,
+    placements: &[(Option<Entity>, NoteState)],
+) -> Option<f32> {
+    let placed = placements
+        .iter()
+        .filter_map(|(note, _)| *note)
+        .collect::<Vec<_>>();
+    // the other notes as they will be after the edit, or `None` if removed
+    let mut others = notes
+        .iter()
+        .filter(|(entity, _, _, _)| !placed.contains(entity))
+        .map(|(entity, note, _, parent)| (entity, Some(NoteState::new(parent.get(), note))))
+        .collect::<Vec<_>>();
 
-            if *seq_state.get() == SequencerState::Stopped {
-                seq_state_next.set(SequencerState::Playing);
+    // The notes placed on a track make room together, so that none is pushed onto another
+    let mut tracks = placements
+        .iter()
+        .map(|(_, placement)| placement.track)
+        .collect::<Vec<_>>();
+    tracks.sort();
+    tracks.dedup();
+    for track in tracks {
+        let placed = placements
+            .iter()
+            .filter(|(_, placement)| placement.track == track)
+            .map(|(_, placement)| (placement.pos, placement.width))
+            .collect::<Vec<_>>();
+        let on_track = others
+            .iter()
+            .filter_map(|&(entity, state)| {
+                let state = state?;
+                (state.track == track).then_some((entity, state.pos, state.width))
+            })
+            .collect::<Vec<_>>();
+        for (entity, span) in overlap::resolve_group(mode, &placed, sequencer.length, &on_track)? {
+            if let Some((_, state)) = others.iter_mut().find(|(other, _)| *other == entity) {
+                *state = span.map(|(pos, width)| NoteState { track, pos, width });
+            }
+        }
+    }
+
+    let mut changed_at = f32::INFINITY;
+    for (entity, after) in others {
+        let Ok((_, mut note, mut style, parent)) = notes.get_mut(entity) else {
+            continue;
+        };
+        let before = NoteState::new(parent.get(), &note);
+        if after == Some(before) {
+            continue;
+        }
+        edit.record(entity, Some(before), after);
+        changed_at = changed_at.min(note.pos);
+        match after {
+            Some(after) => {
+                changed_at = changed_at.min(after.pos);
+                note.pos = after.pos;
+                note.width = after.width;
+                timeline.place(&mut style, after.pos, after.width);
+            }
+            None => {
+                commands.entity(entity).despawn_recursive();
+                sequencer.notes.retain(|&id| id != entity);
             }
         }
     }
+    Some(changed_at)
 }
 
 #[derive(Resource, Clone, Debug, Reflect)]
-struct NoteDragged {
+pub(super) struct NoteDragged {
     note: Entity,
     orig_track: Entity,
-    orig_left: f32,
+    /// [`Note::pos`] of the dragged note, which is only moved when dropped.
+    orig_pos: f32,
+    /// Where the note is dragged to, in seconds.
+    pos: f32,
+    /// The other selected notes, which are moved along.
+    group: Vec<Entity>,
 }
 
-/// Update the position of a dragged note.
+impl NoteDragged {
+    /// Where `note`, at `pos` seconds, is shown while it's dragged, or moved along with
+    /// the dragged note.
+    pub(super) fn dragged_pos(&self, note: Entity, pos: f32) -> Option<f32> {
+        (note == self.note || self.group.contains(&note)).then_some(pos + self.pos - self.orig_pos)
+    }
+}
+
+// Whether the cursor is within `track` horizontally. Notes dropped beside the tracks are removed.
+fn cursor_on_track(
+    track_cursors: &Query<&RelativeCursorPosition, With<Track>>,
+    track: Entity,
+) -> bool {
+    track_cursors
+        .get(track)
+        .ok()
+        .and_then(|rel_cur_pos| rel_cur_pos.normalized)
+        .is_some_and(|pos| (0.0..=1.0).contains(&pos.x))
+}
+
+/// Update the position of a dragged note, and apply it to [`Note::pos`] when dropped.
+/// The other selected notes are moved along by the same time, and the same number of tracks.
 fn note_drag(
     mut commands: Commands,
-    mut notes: Query<(Entity, &Draggable, &Parent, &mut Style), (With<Note>, Changed<Draggable>)>,
-    note_drag: Option<ResMut<NoteDragged>>,
+    drags: Query<(Entity, &Draggable), (With<Note>, Changed<Draggable>)>,
+    mut notes: NoteQuery,
+    selected: Query<Entity, (With<Note>, With<Selected>)>,
+    tracks: Query<(Entity, &Track)>,
+    track_cursors: Query<&RelativeCursorPosition, With<Track>>,
+    note_drag: Option<Res<NoteDragged>>,
+    snapping: Snapping,
+    settings: Res<Settings>,
     mut sequencer: ResMut<Sequencer>,
+    mut history: ResMut<EditHistory>,
+    timeline: Res<Timeline>,
+    seq_state: Res<State<SequencerState>>,
+    mut seq_state_next: ResMut<NextState<SequencerState>>,
 ) {
-    for (entity, draggable, parent, mut style) in notes.iter_mut() {
+    for (entity, draggable) in &drags {
+        let Ok((_, note, _, parent)) = notes.get(entity) else {
+            continue;
+        };
+        let (note, track) = (*note, parent.get());
+
         if matches!(
             draggable.state,
             DragState::DragEnd | DragState::DragCanceled | DragState::Inactive
         ) {
-            if let Some(note_drag) = &note_drag {
-                if note_drag.note == entity {
-                    commands.remove_resource::<NoteDragged>();
-
-                    // If the note is dropped outside of the track, remove it
-                    if let Px(x) = style.left {
-                        if !(0.0..=TRACK_WIDTH).contains(&x) {
-                            commands.entity(entity).despawn_recursive();
-                            sequencer.notes.retain(|&id| id != entity);
-                            // TODO: play sfx when note removed
+            let Some(note_drag) = note_drag.as_deref().filter(|drag| drag.note == entity) else {
+                continue;
+            };
+            commands.remove_resource::<NoteDragged>();
+
+            // If the note is dropped beside the track, remove it along with the group
+            if !cursor_on_track(&track_cursors, track) {
+                let mut edit = Edit::default();
+                let mut changed_at = note.pos;
+                edit.record(
+                    entity,
+                    Some(NoteState::new(note_drag.orig_track, &note)),
+                    None,
+                );
+                for &other in &note_drag.group {
+                    if let Ok((_, other_note, _, other_parent)) = notes.get(other) {
+                        changed_at = changed_at.min(other_note.pos);
+                        let before = NoteState::new(other_parent.get(), other_note);
+                        edit.record(other, Some(before), None);
+                    }
+                }
+                for &id in iter::once(&entity).chain(&note_drag.group) {
+                    commands.entity(id).despawn_recursive();
+                    sequencer.notes.retain(|&note| note != id);
+                }
+                history.push(edit);
+                // TODO: play sfx when note removed
+                sequencer.rewind_to_edit(changed_at, seq_state.get(), &mut seq_state_next);
+                continue;
+            }
+
+            // the cursor may have come back onto the track since the note was last dragged
+            let pos = snapping
+                .snap(note_drag.pos)
+                .clamp(0.0, sequencer.last_note_pos(note.width));
+            if (pos - note.pos).abs() < EPSILON && track == note_drag.orig_track {
+                continue;
+            }
+
+            // The group keeps its relative timing and tracks
+            let delta = pos - note.pos;
+            let track_offset =
+                track_index(&tracks, track) - track_index(&tracks, note_drag.orig_track);
+            let mut placements = vec![(
+                Some(entity),
+                NoteState {
+                    track,
+                    pos,
+                    width: note.width,
+                },
+            )];
+            let mut befores = vec![NoteState::new(note_drag.orig_track, &note)];
+            let mut fits = true;
+            for &other in &note_drag.group {
+                let Ok((_, other_note, _, other_parent)) = notes.get(other) else {
+                    continue;
+                };
+                let other_pos = other_note.pos + delta;
+                let other_track =
+                    shifted_track(&tracks, other_parent.get(), track_offset).filter(|_| {
+                        (0.0..=sequencer.last_note_pos(other_note.width)).contains(&other_pos)
+                    });
+                let Some(other_track) = other_track else {
+                    fits = false;
+                    break;
+                };
+                befores.push(NoteState::new(other_parent.get(), other_note));
+                placements.push((
+                    Some(other),
+                    NoteState {
+                        track: other_track,
+                        pos: other_pos,
+                        width: other_note.width,
+                    },
+                ));
+            }
+
+            let mut edit = Edit::default();
+            let resolved = fits
+                .then(|| {
+                    make_room(
+                        &mut commands,
+                        &mut sequencer,
+                        &timeline,
+                        &mut notes,
+                        &mut edit,
+                        settings.overlap_mode,
+                        &placements,
+                    )
+                })
+                .flatten();
+            match resolved {
+                Some(others_changed_at) => {
+                    let mut changed_at = others_changed_at;
+                    for ((id, after), before) in placements.into_iter().zip(befores) {
+                        let Some(id) = id else {
+                            continue;
+                        };
+                        let Ok((_, mut moved, mut style, _)) = notes.get_mut(id) else {
+                            continue;
+                        };
+                        changed_at = changed_at.min(before.pos).min(after.pos);
+                        moved.pos = after.pos;
+                        style.left = Px(timeline.to_px(after.pos));
+                        if id != entity && after.track != before.track {
+                            commands.entity(id).set_parent(after.track);
+                        }
+                        edit.record(id, Some(before), Some(after));
+                    }
+                    history.push(edit);
+                    sequencer.rewind_to_edit(changed_at, seq_state.get(), &mut seq_state_next);
+                }
+                None => {
+                    // Put the notes back where they were
+                    for &id in iter::once(&entity).chain(&note_drag.group) {
+                        if let Ok((_, note, mut style, _)) = notes.get_mut(id) {
+                            timeline.place(&mut style, note.pos, note.width);
                         }
                     }
+                    commands
+                        .entity(entity)
+                        .set_parent(note_drag.orig_track)
+                        .insert(RejectedEdit::bundle());
                 }
             }
             continue;
         }
 
         if matches!(draggable.state, DragState::DragStart | DragState::Dragging) {
-            let note_drag = if let Some(note_drag) = &note_drag {
-                if note_drag.note != entity {
-                    None
-                } else {
-                    Some(note_drag.deref().clone())
-                }
-            } else {
-                None
-            };
-
-            let note_drag = note_drag.unwrap_or_else(|| {
-                let px = if let Px(px) = style.left { px } else { 0.0 };
-                let nd = NoteDragged {
+            let mut note_drag = match note_drag.as_deref() {
+                Some(note_drag) if note_drag.note == entity => note_drag.clone(),
+                _ => NoteDragged {
                     note: entity,
-                    orig_track: parent.get(),
-                    orig_left: px,
-                };
-                commands.insert_resource(nd.clone());
-                nd
-            });
+                    orig_track: track,
+                    orig_pos: note.pos,
+                    pos: note.pos,
+                    group: if selected.contains(entity) {
+                        selected.iter().filter(|&other| other != entity).collect()
+                    } else {
+                        vec![]
+                    },
+                },
+            };
 
             if let (Some(origin), Some(position)) = (draggable.origin, draggable.position) {
-                let diff = position - origin + note_drag.orig_left;
-                style.left = Px(diff.x);
+                // In seconds rather than pixels, as the view may scroll during the drag
+                let pos = note.pos + timeline.px_to_duration(position.x - origin.x);
+                // Only snap on the track, so that dropping beside it still removes the note
+                note_drag.pos = if cursor_on_track(&track_cursors, track) {
+                    snapping
+                        .snap(pos)
+                        .clamp(0.0, sequencer.last_note_pos(note.width))
+                } else {
+                    pos
+                };
+                for &id in iter::once(&entity).chain(&note_drag.group) {
+                    if let Ok((_, other_note, mut style, _)) = notes.get_mut(id) {
+                        let pos = note_drag
+                            .dragged_pos(id, other_note.pos)
+                            .unwrap_or_default();
+                        style.left = Px(timeline.to_px(pos));
+                    }
+                }
             }
+            commands.insert_resource(note_drag);
 
             break;
         }
     }
 }
 
+/// Index of `track` in the sequencer, from the top.
+/// The tracks of each robot follow those of the previous one.
+pub(super) fn track_index(tracks: &Query<(Entity, &Track)>, track: Entity) -> isize {
+    tracks.get(track).map_or(0, |(_, track)| {
+        let kind_index = NoteKind::ALL
+            .iter()
+            .position(|&kind| kind == track.kind)
+            .unwrap_or(0);
+        (track.robot * NoteKind::ALL.len() + kind_index) as isize
+    })
+}
+
+/// The track `offset` tracks below `track`, or above if negative.
+pub(super) fn shifted_track(
+    tracks: &Query<(Entity, &Track)>,
+    track: Entity,
+    offset: isize,
+) -> Option<Entity> {
+    let index = track_index(tracks, track) + offset;
+    tracks
+        .iter()
+        .find(|&(other, _)| track_index(tracks, other) == index)
+        .map(|(other, _)| other)
+}
+
 // Moves a note to other track during DroppableEntered|DroppableHover
 fn note_move_between_tracks(
     mut commands: Commands,
@@ -260,7 +948,7 @@ fn note_move_between_tracks(
                 );
 
                 // TODO: play sfx when note moved between tracks
-                // TODO: change overlapped note's length?
+                // overlapped notes are resolved when the note is dropped (see `make_room`)
             }
 
             break;
@@ -0,0 +1,298 @@
+//! Keeps the notes on a track from overlapping each other.
+//! When a note is created, moved or resized onto other notes,
+//! they are pushed away or trimmed, or the edit is rejected (see [`OverlapMode`]).
+
+use bevy::color::palettes::tailwind::RED_500;
+use bevy::prelude::*;
+use bevy::ui::Val::*;
+
+use crate::{screen::Screen, settings::OverlapMode};
+
+use super::Note;
+
+pub(super) fn plugin(app: &mut App) {
+    app.add_systems(
+        Update,
+        clear_rejected_edits.run_if(in_state(Screen::Playing)),
+    );
+}
+
+// Notes closer than this are considered touching, not overlapping.
+pub(super) const EPSILON: f32 = 1e-4;
+
+/// A change to another note: its new position and width, or `None` if it is removed.
+pub(super) type Change = (Entity, Option<(f32, f32)>);
+
+// Whether `pos..pos + width` and `other_pos..other_pos + other_width` overlap.
+fn overlap(pos: f32, width: f32, other_pos: f32, other_width: f32) -> bool {
+    other_pos < pos + width - EPSILON && other_pos + other_width > pos + EPSILON
+}
+
+/// Changes to `others`, given as `(entity, pos, width)`, that make room for notes placed
+/// together on their track, given as `(pos, width)`, on a tape `length` seconds long,
+/// or `None` if the edit has to be rejected. Neither may overlap each other.
+pub(super) fn resolve_group(
+    mode: OverlapMode,
+    placed: &[(f32, f32)],
+    length: f32,
+    others: &[(Entity, f32, f32)],
+) -> Option<Vec<Change>> {
+    // the others as they are after the changes so far, or `None` if removed
+    let mut spans = others
+        .iter()
+        .map(|&(entity, pos, width)| (entity, Some((pos, width))))
+        .collect::<Vec<_>>();
+    for &(pos, width) in placed {
+        let remaining = spans
+            .iter()
+            .filter_map(|&(entity, span)| span.map(|(pos, width)| (entity, pos, width)))
+            .collect::<Vec<_>>();
+        for (entity, span) in resolve_around(mode, pos, width, length, &remaining, placed)? {
+            if let Some((_, other_span)) = spans.iter_mut().find(|(other, _)| *other == entity) {
+                *other_span = span;
+            }
+        }
+    }
+
+    // Making room for a placed note must not have moved others onto a note placed before
+    let still_overlapping = spans
+        .iter()
+        .filter_map(|(_, span)| *span)
+        .any(|(pos, width)| {
+            placed
+                .iter()
+                .any(|&(placed_pos, placed_width)| overlap(placed_pos, placed_width, pos, width))
+        });
+    if still_overlapping {
+        return None;
+    }
+    Some(
+        spans
+            .into_iter()
+            .zip(others)
+            .filter(|((_, span), &(_, pos, width))| *span != Some((pos, width)))
+            .map(|(change, _)| change)
+            .collect(),
+    )
+}
+
+/// Changes to `others`, given as `(entity, pos, width)`, that make room for a note
+/// spanning `pos..pos + width` on a tape `length` seconds long,
+/// or `None` if the edit has to be rejected. `others` must not overlap each other.
+#[cfg(test)]
+fn resolve(
+    mode: OverlapMode,
+    pos: f32,
+    width: f32,
+    length: f32,
+    others: &[(Entity, f32, f32)],
+) -> Option<Vec<Change>> {
+    resolve_around(mode, pos, width, length, others, &[])
+}
+
+// As `resolve`, with notes pushed past the spans in `placed` instead of onto them.
+fn resolve_around(
+    mode: OverlapMode,
+    pos: f32,
+    width: f32,
+    length: f32,
+    others: &[(Entity, f32, f32)],
+    placed: &[(f32, f32)],
+) -> Option<Vec<Change>> {
+    let end = pos + width;
+    let overlaps = |&&(_, other_pos, other_width): &&(Entity, f32, f32)| {
+        overlap(pos, width, other_pos, other_width)
+    };
+    let placed_over = |pos: f32, width: f32| {
+        placed
+            .iter()
+            .find(|&&(placed_pos, placed_width)| overlap(placed_pos, placed_width, pos, width))
+            .copied()
+    };
+
+    match mode {
+        OverlapMode::Reject => (!others.iter().any(|other| overlaps(&other))).then(Vec::new),
+        OverlapMode::Trim => Some(
+            others
+                .iter()
+                .filter(overlaps)
+                .map(|&(entity, other_pos, other_width)| {
+                    // keep the part before the edited note, or else the part after it
+                    let (new_pos, new_width) = if other_pos < pos {
+                        (other_pos, pos - other_pos)
+                    } else {
+                        (end, other_pos + other_width - end)
+                    };
+                    let span = (new_width >= Note::MIN_WIDTH).then_some((new_pos, new_width));
+                    (entity, span)
+                })
+                .collect(),
+        ),
+        OverlapMode::Push => {
+            let mut changes = vec![];
+
+            // Notes starting within or after the edited note are pushed to the right,
+            // each pushing the next one if needed.
+            let mut after = others
+                .iter()
+                .filter(|(_, other_pos, _)| *other_pos >= pos)
+                .collect::<Vec<_>>();
+            after.sort_by(|a, b| a.1.total_cmp(&b.1));
+            let mut limit = end;
+            for &&(entity, other_pos, other_width) in &after {
+                if other_pos >= limit - EPSILON {
+                    break;
+                }
+                let mut new_pos = limit;
+                while let Some((placed_pos, placed_width)) = placed_over(new_pos, other_width) {
+                    new_pos = placed_pos + placed_width;
+                }
+                if new_pos + other_width > length + EPSILON {
+                    return None;
+                }
+                changes.push((entity, Some((new_pos, other_width))));
+                limit = new_pos + other_width;
+            }
+
+            // ...and the others to the left.
+            let mut before = others
+                .iter()
+                .filter(|(_, other_pos, _)| *other_pos < pos)
+                .collect::<Vec<_>>();
+            before.sort_by(|a, b| b.1.total_cmp(&a.1));
+            let mut limit = pos;
+            for &&(entity, other_pos, other_width) in &before {
+                if other_pos + other_width <= limit + EPSILON {
+                    break;
+                }
+                let mut new_pos = limit - other_width;
+                while let Some((placed_pos, _)) = placed_over(new_pos, other_width) {
+                    new_pos = placed_pos - other_width;
+                }
+                if new_pos < -EPSILON {
+                    return None;
+                }
+                limit = new_pos;
+                changes.push((entity, Some((new_pos, other_width))));
+            }
+
+            Some(changes)
+        }
+    }
+}
+
+/// Outlines a note or a track for a moment after an edit of it was rejected.
+#[derive(Component)]
+pub(super) struct RejectedEdit(Timer);
+
+impl RejectedEdit {
+    pub(super) fn bundle() -> impl Bundle {
+        (
+            RejectedEdit(Timer::from_seconds(0.4, TimerMode::Once)),
+            Outline::new(Px(2.0), Px(0.0), RED_500.into()),
+        )
+    }
+}
+
+fn clear_rejected_edits(
+    mut commands: Commands,
+    time: Res<Time>,
+    mut rejected: Query<(Entity, &mut RejectedEdit)>,
+) {
+    for (entity, mut rejected) in &mut rejected {
+        if rejected.0.tick(time.delta()).finished() {
+            commands.entity(entity).remove::<(RejectedEdit, Outline)>();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const LENGTH: f32 = 10.0;
+
+    fn entity(index: u32) -> Entity {
+        Entity::from_raw(index)
+    }
+
+    #[test]
+    fn touching_notes_dont_overlap() {
+        let others = [(entity(0), 0.0, 2.0), (entity(1), 4.0, 2.0)];
+        for mode in [OverlapMode::Push, OverlapMode::Trim, OverlapMode::Reject] {
+            assert_eq!(resolve(mode, 2.0, 2.0, LENGTH, &others), Some(vec![]));
+        }
+    }
+
+    #[test]
+    fn reject_refuses_overlaps() {
+        let others = [(entity(0), 3.0, 2.0)];
+        assert_eq!(
+            resolve(OverlapMode::Reject, 2.0, 2.0, LENGTH, &others),
+            None
+        );
+    }
+
+    #[test]
+    fn trim_keeps_the_part_outside() {
+        let others = [(entity(0), 1.0, 2.0), (entity(1), 3.5, 2.0)];
+        assert_eq!(
+            resolve(OverlapMode::Trim, 2.0, 2.0, LENGTH, &others),
+            Some(vec![
+                (entity(0), Some((1.0, 1.0))),
+                (entity(1), Some((4.0, 1.5))),
+            ])
+        );
+    }
+
+    #[test]
+    fn trim_removes_what_is_too_short() {
+        let others = [(entity(0), 1.9, 0.2), (entity(1), 3.0, 1.1)];
+        assert_eq!(
+            resolve(OverlapMode::Trim, 2.0, 2.0, LENGTH, &others),
+            Some(vec![(entity(0), None), (entity(1), None)])
+        );
+    }
+
+    #[test]
+    fn push_moves_notes_aside_in_a_chain() {
+        let others = [
+            (entity(0), 1.0, 2.0),
+            (entity(1), 3.0, 1.0),
+            (entity(2), 4.5, 1.0),
+            (entity(3), 8.0, 1.0),
+        ];
+        assert_eq!(
+            resolve(OverlapMode::Push, 2.5, 2.0, LENGTH, &others),
+            Some(vec![
+                (entity(1), Some((4.5, 1.0))),
+                (entity(2), Some((5.5, 1.0))),
+                (entity(0), Some((0.5, 2.0))),
+            ])
+        );
+    }
+
+    #[test]
+    fn push_rejects_pushing_off_the_tape() {
+        let after = [(entity(0), 8.5, 1.0)];
+        assert_eq!(resolve(OverlapMode::Push, 8.0, 1.5, LENGTH, &after), None);
+        let before = [(entity(0), 0.0, 1.0)];
+        assert_eq!(resolve(OverlapMode::Push, 0.5, 1.0, LENGTH, &before), None);
+    }
+
+    #[test]
+    fn push_moves_notes_past_the_rest_of_the_group() {
+        // pushed left by the second placed note, the other would land on the first
+        let placed = [(0.0, 1.0), (1.2, 1.0)];
+        let others = [(entity(0), 0.5, 0.5)];
+        assert_eq!(
+            resolve_group(OverlapMode::Push, &placed, LENGTH, &others),
+            Some(vec![(entity(0), Some((2.2, 0.5)))])
+        );
+        // with no room past the group, the edit is rejected
+        assert_eq!(
+            resolve_group(OverlapMode::Push, &placed, 2.5, &others),
+            None
+        );
+    }
+}
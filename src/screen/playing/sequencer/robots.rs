@@ -0,0 +1,151 @@
+//! The tracks of each robot of the level.
+//!
+//! A level may place several robots (see [`RobotIndex`]). Each has its own set of tracks in the
+//! sequencer, one per kind of note, following those of the previous robot, and only acts on
+//! the notes on them. When there is more than one robot, the labels of its tracks take its color.
+
+use bevy::color::palettes::css::WHITE;
+use bevy::color::palettes::tailwind::BLUE_200;
+use bevy::prelude::*;
+use bevy::ui::{RelativeCursorPosition, Val::*};
+use bevy_ecs_ldtk::prelude::*;
+use sickle_ui_scaffold::prelude::{Draggable, DropZone, TrackedInteraction};
+
+use crate::game::player::{Player, RobotIndex};
+use crate::screen::Screen;
+
+use super::{EditCursor, Note, NoteKind, Sequencer, Timeline, Track, TRACK_WIDTH};
+
+pub(super) fn plugin(app: &mut App) {
+    app.add_systems(
+        Update,
+        apply_level_robots.run_if(in_state(Screen::Playing).and_then(resource_exists::<Sequencer>)),
+    );
+}
+
+/// The label in front of a track, naming its kind of notes.
+#[derive(Component)]
+struct TrackLabel {
+    robot: usize,
+}
+
+// Space between the tracks of a robot and those of the next one, in pixels.
+const ROBOT_GAP: f32 = 10.0;
+
+fn label_color(robot: usize, robots: usize) -> Color {
+    if robots > 1 {
+        RobotIndex(robot).color()
+    } else {
+        WHITE.into()
+    }
+}
+
+/// Spawns the labels and tracks of the robot `robot`, in a level of `robots` robots.
+pub(super) fn spawn_robot_tracks(spawner: &mut ChildBuilder, robot: usize, robots: usize) {
+    for (i, kind) in NoteKind::ALL.into_iter().enumerate() {
+        let margin = if robot > 0 && i == 0 {
+            UiRect::top(Px(ROBOT_GAP))
+        } else {
+            UiRect::ZERO
+        };
+
+        spawner.spawn((
+            TextBundle::from_section(
+                kind.label(),
+                TextStyle {
+                    font_size: 24.0,
+                    color: label_color(robot, robots),
+                    ..default()
+                },
+            )
+            .with_style(Style {
+                margin,
+                ..default()
+            }),
+            TrackLabel { robot },
+        ));
+
+        spawner.spawn((
+            Name::new(format!("Track {} {}", robot, kind.label())),
+            Track { kind, robot },
+            NodeBundle {
+                style: Style {
+                    width: Px(TRACK_WIDTH),
+                    height: Px(50.0),
+                    border: UiRect::all(Px(3.0)),
+                    margin,
+                    overflow: Overflow::clip(),
+                    ..default()
+                },
+                border_color: BLUE_200.into(),
+                ..default()
+            },
+            Interaction::None,
+            TrackedInteraction::default(),
+            // dragging on a track creates a note spanning the drag
+            Draggable::default(),
+            DropZone::default(),
+            RelativeCursorPosition::default(),
+        ));
+    }
+}
+
+// Each level has its own robots, and the sequencer a set of tracks for each
+fn apply_level_robots(
+    mut commands: Commands,
+    mut level_events: EventReader<LevelEvent>,
+    players: Query<&RobotIndex, With<Player>>,
+    tracks: Query<(Entity, &Track)>,
+    mut labels: Query<(Entity, &TrackLabel, &mut Text)>,
+    notes: Query<&Note>,
+    cursor: Option<Res<EditCursor>>,
+    mut sequencer: ResMut<Sequencer>,
+    mut timeline: ResMut<Timeline>,
+) {
+    for event in level_events.read() {
+        if !matches!(event, LevelEvent::Spawned(_)) {
+            continue;
+        }
+        let robots = players.iter().map(|robot| robot.0 + 1).max().unwrap_or(1);
+        if robots == sequencer.robots {
+            continue;
+        }
+
+        // The tracks of robots the level doesn't have go, along with their notes
+        let removed = tracks
+            .iter()
+            .filter(|(_, track)| track.robot >= robots)
+            .map(|(entity, _)| entity)
+            .collect::<Vec<_>>();
+        for &track in &removed {
+            commands.entity(track).despawn_recursive();
+        }
+        sequencer
+            .notes
+            .retain(|&id| notes.get(id).is_ok_and(|note| note.robot < robots));
+        if cursor
+            .as_ref()
+            .is_some_and(|cursor| removed.contains(&cursor.track()))
+        {
+            commands.remove_resource::<EditCursor>();
+        }
+
+        for (entity, label, mut text) in &mut labels {
+            if label.robot >= robots {
+                commands.entity(entity).despawn_recursive();
+            } else {
+                text.sections[0].style.color = label_color(label.robot, robots);
+            }
+        }
+
+        let added = sequencer.robots..robots;
+        commands.entity(sequencer.id).with_children(|children| {
+            for robot in added {
+                spawn_robot_tracks(children, robot, robots);
+            }
+        });
+        sequencer.robots = robots;
+        // draws the grid lines on the new tracks
+        timeline.set_changed();
+    }
+}
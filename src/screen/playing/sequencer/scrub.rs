@@ -0,0 +1,134 @@
+//! Scrubbing the tape with the handle of the seek bar.
+//!
+//! While the handle is dragged the tape is held in `SequencerState::Seeking`, and the play
+//! position follows the handle. Each move triggers [`Scrub`], on which `game::rewind` restores
+//! the world, so it previews the scrubbed position. Releasing the handle plays on from there,
+//! or stays paused or stopped if the tape was.
+
+use bevy::color::palettes::tailwind::BLUE_50;
+use bevy::prelude::*;
+use bevy::ui::{FocusPolicy, Val::*};
+use sickle_ui_scaffold::prelude::{DragState, Draggable, DraggableUpdate, TrackedInteraction};
+
+use crate::screen::{playing::SequencerState, Screen};
+
+use super::{NoteCreation, Sequencer, Timeline};
+
+pub(super) fn plugin(app: &mut App) {
+    app.add_systems(OnExit(Screen::Playing), remove_scrubbing);
+    app.add_systems(
+        Update,
+        scrub_seek_bar.after(DraggableUpdate).run_if(
+            in_state(Screen::Playing)
+                .and_then(not(in_state(SequencerState::Cleared)))
+                .and_then(not(resource_exists::<NoteCreation>)),
+        ),
+    );
+}
+
+/// Triggered when the tape is scrubbed to a play position, in seconds.
+#[derive(Event, Debug)]
+pub struct Scrub(pub f32);
+
+/// The handle on top of the seek bar.
+#[derive(Component, Debug)]
+struct SeekHandle;
+
+/// Present while the handle of the seek bar is dragged.
+#[derive(Resource, Debug)]
+pub(super) struct Scrubbing {
+    /// Play position when the drag started.
+    from: f32,
+}
+
+// Size of the handle in pixels.
+const HANDLE_SIZE: f32 = 15.0;
+
+/// Spawns the handle as a child of the seek bar, so that it moves along with it.
+pub(super) fn spawn_seek_handle(spawner: &mut ChildBuilder, bar_width: f32) {
+    spawner.spawn((
+        Name::new("Seek Handle"),
+        NodeBundle {
+            style: Style {
+                position_type: PositionType::Absolute,
+                left: Px((bar_width - HANDLE_SIZE) * 0.5),
+                width: Px(HANDLE_SIZE),
+                height: Px(HANDLE_SIZE),
+                ..default()
+            },
+            background_color: BLUE_50.into(),
+            focus_policy: FocusPolicy::Block,
+            ..default()
+        },
+        Interaction::None,
+        TrackedInteraction::default(),
+        Draggable::default(),
+        SeekHandle,
+    ));
+}
+
+fn remove_scrubbing(mut commands: Commands) {
+    commands.remove_resource::<Scrubbing>();
+}
+
+// The tape can only be scrubbed forward as far as it has been played; when released past that,
+// the play position snaps back to the last recorded frame (see `game::rewind`).
+fn scrub_seek_bar(
+    mut commands: Commands,
+    handles: Query<&Draggable, (With<SeekHandle>, Changed<Draggable>)>,
+    scrubbing: Option<Res<Scrubbing>>,
+    mut sequencer: ResMut<Sequencer>,
+    timeline: Res<Timeline>,
+    seq_state: Res<State<SequencerState>>,
+    mut seq_state_next: ResMut<NextState<SequencerState>>,
+) {
+    for draggable in &handles {
+        match draggable.state {
+            DragState::DragStart | DragState::Dragging => {
+                let from = match &scrubbing {
+                    Some(scrubbing) => scrubbing.from,
+                    None => {
+                        let from = sequencer.play_pos;
+                        commands.insert_resource(Scrubbing { from });
+                        // Physics is paused and notes are not played while scrubbing.
+                        // When released, the tape goes back to what it was doing.
+                        let then = match seq_state.get() {
+                            SequencerState::Seeking => sequencer.after_seek.clone(),
+                            SequencerState::Paused => SequencerState::Paused,
+                            SequencerState::Stopped => SequencerState::Stopped,
+                            _ => SequencerState::Playing,
+                        };
+                        sequencer.seek_and(from, then, &mut seq_state_next);
+                        from
+                    }
+                };
+                let (Some(origin), Some(position)) = (draggable.origin, draggable.position) else {
+                    continue;
+                };
+                let target = (from + timeline.px_to_duration(position.x - origin.x))
+                    .clamp(0.0, sequencer.length);
+                scrub_to(&mut commands, &mut sequencer, target);
+            }
+            DragState::DragEnd | DragState::DragCanceled => {
+                let Some(scrubbing) = &scrubbing else {
+                    continue;
+                };
+                if draggable.state == DragState::DragCanceled {
+                    scrub_to(&mut commands, &mut sequencer, scrubbing.from);
+                }
+                // `advance_seek` moves on right away, as the seek target is reached
+                commands.remove_resource::<Scrubbing>();
+            }
+            _ => {}
+        }
+    }
+}
+
+fn scrub_to(commands: &mut Commands, sequencer: &mut Sequencer, time: f32) {
+    if sequencer.play_pos == time {
+        return;
+    }
+    sequencer.play_pos = time;
+    sequencer.seek_target = time;
+    commands.trigger(Scrub(time));
+}
@@ -0,0 +1,358 @@
+//! Selection of notes, and copying them around.
+//!
+//! Notes are selected by pressing them, with a key held to add or remove them from the selection,
+//! or by dragging a rubber band over them from a track with that key held
+//! (see [`KeyBindings::extend_selection`]).
+//! Selected notes are dragged together (see `notes::note_drag`), and can be copied,
+//! pasted at the play position and duplicated, keeping their timing and tracks.
+
+use bevy::color::palettes::tailwind::{BLUE_200, BLUE_600};
+use bevy::prelude::*;
+use bevy::ui::Val::*;
+use bevy::window::PrimaryWindow;
+use sickle_ui_scaffold::prelude::{DraggableUpdate, FluxInteraction, FluxInteractionUpdate};
+
+use crate::game::spawn::level::NextLevel;
+use crate::screen::{playing::SequencerState, Screen};
+use crate::settings::{modifier_pressed, KeyBindings, Settings};
+
+use super::history::{Edit, EditHistory, NoteState};
+use super::notes::{make_room, NoteHandle, NoteQuery};
+use super::overlap::RejectedEdit;
+use super::{Note, NoteCreation, Sequencer, Snapping, Timeline, Track};
+
+pub(super) fn plugin(app: &mut App) {
+    app.add_systems(
+        Update,
+        (
+            select_on_press.run_if(not(resource_exists::<RubberBand>)),
+            update_rubber_band.run_if(resource_exists::<RubberBand>),
+            clipboard_keys.run_if(
+                not(resource_exists::<NoteCreation>)
+                    .and_then(not(in_state(SequencerState::Cleared))),
+            ),
+        )
+            .chain()
+            .after(FluxInteractionUpdate)
+            .after(DraggableUpdate)
+            .run_if(in_state(Screen::Playing)),
+    );
+    app.add_systems(PostUpdate, show_selection.run_if(in_state(Screen::Playing)));
+    app.add_systems(OnExit(Screen::Playing), clear_clipboard);
+    app.observe(clear_clipboard_on_next_level);
+}
+
+/// A note that is selected.
+#[derive(Component, Debug)]
+pub(super) struct Selected;
+
+/// Whether the key to add to the selection is held.
+pub(super) fn is_extending(keys: &ButtonInput<KeyCode>, bindings: &KeyBindings) -> bool {
+    modifier_pressed(keys, bindings.extend_selection)
+}
+
+// Pressing a note selects it, and pressing a track to create a note clears the selection.
+// While extending, pressing a note toggles it, and pressing a track starts a rubber band.
+fn select_on_press(
+    mut commands: Commands,
+    notes: Query<(Entity, &FluxInteraction, Has<Selected>), (With<Note>, Changed<FluxInteraction>)>,
+    handles: Query<&Interaction, With<NoteHandle>>,
+    tracks: Query<&FluxInteraction, (With<Track>, Changed<FluxInteraction>)>,
+    selected: Query<Entity, With<Selected>>,
+    keys: Res<ButtonInput<KeyCode>>,
+    settings: Res<Settings>,
+    windows: Query<&Window, With<PrimaryWindow>>,
+) {
+    let extending = is_extending(&keys, &settings.key_bindings);
+
+    if let Some((entity, _, is_selected)) = notes
+        .iter()
+        .find(|(_, interaction, _)| interaction.is_pressed())
+    {
+        if extending {
+            if is_selected {
+                commands.entity(entity).remove::<Selected>();
+            } else {
+                commands.entity(entity).insert(Selected);
+            }
+        } else if !is_selected {
+            // keep the selection when pressing a selected note, to drag them all
+            for other in &selected {
+                commands.entity(other).remove::<Selected>();
+            }
+            commands.entity(entity).insert(Selected);
+        }
+        return;
+    }
+
+    let handle_pressed = handles
+        .iter()
+        .any(|&interaction| interaction != Interaction::None);
+    if handle_pressed || !tracks.iter().any(FluxInteraction::is_pressed) {
+        return;
+    }
+
+    if extending {
+        let Some(cursor) = windows.get_single().ok().and_then(Window::cursor_position) else {
+            return;
+        };
+        let node = commands
+            .spawn((
+                Name::new("Rubber Band"),
+                NodeBundle {
+                    style: Style {
+                        position_type: PositionType::Absolute,
+                        left: Px(cursor.x),
+                        top: Px(cursor.y),
+                        border: UiRect::all(Px(1.0)),
+                        ..default()
+                    },
+                    background_color: BLUE_200.with_alpha(0.2).into(),
+                    border_color: BLUE_200.into(),
+                    z_index: ZIndex::Global(5),
+                    ..default()
+                },
+                StateScoped(Screen::Playing),
+            ))
+            .id();
+        commands.insert_resource(RubberBand {
+            start: cursor,
+            node,
+        });
+    } else {
+        for other in &selected {
+            commands.entity(other).remove::<Selected>();
+        }
+    }
+}
+
+/// A rectangle being dragged to select the notes under it.
+#[derive(Resource, Debug)]
+struct RubberBand {
+    /// Where the drag started, in logical pixels of the window.
+    start: Vec2,
+    node: Entity,
+}
+
+fn update_rubber_band(
+    mut commands: Commands,
+    band: Res<RubberBand>,
+    mut styles: Query<&mut Style>,
+    notes: Query<(Entity, &Node, &GlobalTransform), With<Note>>,
+    mouse: Res<ButtonInput<MouseButton>>,
+    windows: Query<&Window, With<PrimaryWindow>>,
+) {
+    if mouse.just_pressed(MouseButton::Right) {
+        commands.entity(band.node).despawn_recursive();
+        commands.remove_resource::<RubberBand>();
+        return;
+    }
+
+    let cursor = windows
+        .get_single()
+        .ok()
+        .and_then(Window::cursor_position)
+        .unwrap_or(band.start);
+    let rect = Rect::from_corners(band.start, cursor);
+
+    if mouse.just_released(MouseButton::Left) {
+        for (entity, node, gt) in &notes {
+            if !rect.intersect(node.logical_rect(gt)).is_empty() {
+                commands.entity(entity).insert(Selected);
+            }
+        }
+        commands.entity(band.node).despawn_recursive();
+        commands.remove_resource::<RubberBand>();
+        return;
+    }
+
+    if let Ok(mut style) = styles.get_mut(band.node) {
+        style.left = Px(rect.min.x);
+        style.top = Px(rect.min.y);
+        style.width = Px(rect.width());
+        style.height = Px(rect.height());
+    }
+}
+
+// Run this in PostUpdate, after the selection is changed by commands
+fn show_selection(mut notes: Query<(&mut BorderColor, Has<Selected>), With<Note>>) {
+    for (mut border, selected) in &mut notes {
+        let color = if selected {
+            BLUE_600.into()
+        } else {
+            Color::NONE
+        };
+        border.set_if_neq(BorderColor(color));
+    }
+}
+
+/// A copied note, relative to the earliest copied note.
+#[derive(Clone, Copy, Debug)]
+struct CopiedNote {
+    track: Entity,
+    /// Seconds after the earliest copied note.
+    offset: f32,
+    width: f32,
+}
+
+/// Notes copied with Ctrl+C, to be pasted with Ctrl+V.
+#[derive(Resource, Debug)]
+struct Clipboard(Vec<CopiedNote>);
+
+impl Clipboard {
+    fn copy(notes: impl Iterator<Item = NoteState>) -> (Self, f32) {
+        let notes = notes.collect::<Vec<_>>();
+        let start = notes
+            .iter()
+            .map(|note| note.pos)
+            .fold(f32::INFINITY, f32::min);
+        let copied = notes
+            .iter()
+            .map(|note| CopiedNote {
+                track: note.track,
+                offset: note.pos - start,
+                width: note.width,
+            })
+            .collect();
+        (Self(copied), start)
+    }
+
+    /// Seconds from the start of the earliest note to the end of the latest.
+    fn length(&self) -> f32 {
+        self.0
+            .iter()
+            .map(|note| note.offset + note.width)
+            .fold(0.0, f32::max)
+    }
+}
+
+fn clear_clipboard(mut commands: Commands) {
+    commands.remove_resource::<Clipboard>();
+}
+
+// The copied notes were on the tracks of the previous level
+fn clear_clipboard_on_next_level(_trigger: Trigger<NextLevel>, commands: Commands) {
+    clear_clipboard(commands);
+}
+
+// Ctrl+C copies the selected notes, Ctrl+V pastes them at the play position,
+// and Ctrl+D duplicates them right after themselves.
+fn clipboard_keys(
+    mut commands: Commands,
+    keys: Res<ButtonInput<KeyCode>>,
+    clipboard: Option<Res<Clipboard>>,
+    selected: Query<Entity, With<Selected>>,
+    mut notes: NoteQuery,
+    tracks: Query<&Track>,
+    snapping: Snapping,
+    settings: Res<Settings>,
+    mut sequencer: ResMut<Sequencer>,
+    mut history: ResMut<EditHistory>,
+    timeline: Res<Timeline>,
+    seq_state: Res<State<SequencerState>>,
+    mut seq_state_next: ResMut<NextState<SequencerState>>,
+) {
+    let bindings = &settings.key_bindings;
+    if !modifier_pressed(&keys, bindings.clipboard_modifier) {
+        return;
+    }
+    let selection = || {
+        Clipboard::copy(selected.iter().filter_map(|entity| {
+            let (_, note, _, parent) = notes.get(entity).ok()?;
+            Some(NoteState::new(parent.get(), note))
+        }))
+    };
+
+    let (pasted, at) = if keys.just_pressed(bindings.copy) {
+        let (copied, _) = selection();
+        if !copied.0.is_empty() {
+            commands.insert_resource(copied);
+        }
+        return;
+    } else if keys.just_pressed(bindings.paste) {
+        let Some(clipboard) = clipboard else {
+            return;
+        };
+        // tracks may have gone since the notes were copied
+        let copied = clipboard
+            .0
+            .iter()
+            .filter(|note| tracks.contains(note.track))
+            .copied()
+            .collect();
+        (Clipboard(copied), snapping.snap(sequencer.play_pos))
+    } else if keys.just_pressed(bindings.duplicate) {
+        let (copied, start) = selection();
+        let at = start + copied.length();
+        (copied, at)
+    } else {
+        return;
+    };
+    if pasted.0.is_empty() {
+        return;
+    }
+
+    // paste as much as fits before the end of the tape
+    let at = at.min(sequencer.length - pasted.length());
+    let placements = pasted
+        .0
+        .iter()
+        .map(|note| {
+            let state = NoteState {
+                track: note.track,
+                pos: at + note.offset,
+                width: note.width,
+            };
+            (None, state)
+        })
+        .collect::<Vec<_>>();
+
+    let mut edit = Edit::default();
+    let resolved = (at >= 0.0)
+        .then(|| {
+            make_room(
+                &mut commands,
+                &mut sequencer,
+                &timeline,
+                &mut notes,
+                &mut edit,
+                settings.overlap_mode,
+                &placements,
+            )
+        })
+        .flatten();
+    let Some(others_changed_at) = resolved else {
+        for (_, state) in &placements {
+            commands.entity(state.track).insert(RejectedEdit::bundle());
+        }
+        return;
+    };
+
+    // The pasted notes become the selection
+    for entity in &selected {
+        commands.entity(entity).remove::<Selected>();
+    }
+    for (_, state) in placements {
+        let Ok(track) = tracks.get(state.track) else {
+            continue;
+        };
+        commands.entity(state.track).with_children(|children| {
+            let id = Note::spawn(children, *track, state.pos, state.width)
+                .insert(Selected)
+                .id();
+            sequencer.notes.push(id);
+            edit.record(id, None, Some(state));
+        });
+    }
+    history.push(edit);
+
+    if *seq_state.get() == SequencerState::Stopped {
+        seq_state_next.set(SequencerState::Playing);
+    } else {
+        sequencer.rewind_to_edit(
+            at.min(others_changed_at),
+            seq_state.get(),
+            &mut seq_state_next,
+        );
+    }
+}
@@ -0,0 +1,282 @@
+//! A plain data model of the notes in the sequencer, decoupled from the UI entities,
+//! so that a solution can be saved to (and loaded from) a RON file.
+
+use std::{fmt, io, path::Path};
+
+#[cfg(feature = "dev_native")]
+use bevy::input::common_conditions::input_just_pressed;
+use bevy::prelude::*;
+#[cfg(feature = "dev_native")]
+use bevy_ecs_ldtk::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::screen::playing::SequencerState;
+#[cfg(feature = "dev_native")]
+use crate::{
+    game::assets::{HandleMap, LdtkKey},
+    screen::Screen,
+};
+
+use super::{Note, NoteKind, Sequencer, Track};
+
+pub(super) fn plugin(app: &mut App) {
+    app.observe(load_sequence);
+
+    #[cfg(feature = "dev_native")]
+    app.add_systems(
+        Update,
+        (
+            save_solution.run_if(input_just_pressed(KeyCode::F5)),
+            load_solution.run_if(input_just_pressed(KeyCode::F9)),
+        )
+            .run_if(in_state(Screen::Playing)),
+    );
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Default)]
+pub struct Sequence {
+    /// Length of the tape in seconds.
+    pub length: f32,
+    pub tracks: Vec<TrackData>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct TrackData {
+    pub kind: NoteKind,
+    /// Index of the robot playing the track. Sequences saved before levels had
+    /// several robots are all for the first one.
+    #[serde(default)]
+    pub robot: usize,
+    pub notes: Vec<NoteData>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+pub struct NoteData {
+    /// See [`Note::pos`].
+    pub pos: f32,
+    /// See [`Note::width`].
+    pub width: f32,
+}
+
+#[derive(Debug)]
+pub enum SequenceError {
+    Io(io::Error),
+    Serialize(ron::Error),
+    Parse(ron::error::SpannedError),
+}
+
+impl fmt::Display for SequenceError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SequenceError::Io(e) => write!(f, "I/O error: {e}"),
+            SequenceError::Serialize(e) => write!(f, "failed to serialize: {e}"),
+            SequenceError::Parse(e) => write!(f, "failed to parse: {e}"),
+        }
+    }
+}
+
+impl std::error::Error for SequenceError {}
+
+impl From<io::Error> for SequenceError {
+    fn from(e: io::Error) -> Self {
+        SequenceError::Io(e)
+    }
+}
+
+impl From<ron::Error> for SequenceError {
+    fn from(e: ron::Error) -> Self {
+        SequenceError::Serialize(e)
+    }
+}
+
+impl From<ron::error::SpannedError> for SequenceError {
+    fn from(e: ron::error::SpannedError) -> Self {
+        SequenceError::Parse(e)
+    }
+}
+
+impl Sequence {
+    /// Notes of `kind` played by `robot` in the sequence, in no particular order.
+    pub fn notes_of(&self, robot: usize, kind: NoteKind) -> impl Iterator<Item = &NoteData> {
+        self.tracks
+            .iter()
+            .filter(move |track| track.robot == robot && track.kind == kind)
+            .flat_map(|track| &track.notes)
+    }
+
+    /// Notes played when the play position is at `time` seconds.
+    pub fn playing_at(&self, time: f32) -> Vec<Note> {
+        self.tracks
+            .iter()
+            .flat_map(|track| {
+                track.notes.iter().map(|note| Note {
+                    kind: track.kind,
+                    robot: track.robot,
+                    pos: note.pos,
+                    width: note.width,
+                })
+            })
+            .filter(|note| note.is_playing_at(time))
+            .collect()
+    }
+
+    pub fn to_ron(&self) -> Result<String, SequenceError> {
+        Ok(ron::ser::to_string_pretty(
+            self,
+            ron::ser::PrettyConfig::default(),
+        )?)
+    }
+
+    pub fn from_ron(s: &str) -> Result<Self, SequenceError> {
+        Ok(ron::from_str(s)?)
+    }
+
+    pub fn save(&self, path: impl AsRef<Path>) -> Result<(), SequenceError> {
+        let path = path.as_ref();
+        if let Some(dir) = path.parent() {
+            std::fs::create_dir_all(dir)?;
+        }
+        std::fs::write(path, self.to_ron()?)?;
+        Ok(())
+    }
+
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, SequenceError> {
+        Self::from_ron(&std::fs::read_to_string(path)?)
+    }
+}
+
+/// Directory of solution files, next to the level files.
+pub const SOLUTIONS_DIR: &str = "assets/levels/solutions";
+
+/// Path of the solution file of a level.
+pub fn solution_path(level_identifier: &str) -> String {
+    format!("{SOLUTIONS_DIR}/{level_identifier}.ron")
+}
+
+impl Sequencer {
+    /// Collects the notes in the sequencer UI.
+    pub fn capture(&self, notes: &Query<&Note>) -> Sequence {
+        let tracks = (0..self.robots)
+            .flat_map(|robot| NoteKind::ALL.map(|kind| (robot, kind)))
+            .map(|(robot, kind)| {
+                let mut track_notes = self
+                    .notes
+                    .iter()
+                    .filter_map(|&id| notes.get(id).ok())
+                    .filter(|note| note.robot == robot && note.kind == kind)
+                    .map(|note| NoteData {
+                        pos: note.pos,
+                        width: note.width,
+                    })
+                    .collect::<Vec<_>>();
+                track_notes.sort_by(|a, b| a.pos.total_cmp(&b.pos));
+                TrackData {
+                    kind,
+                    robot,
+                    notes: track_notes,
+                }
+            })
+            .collect();
+
+        Sequence {
+            length: self.length,
+            tracks,
+        }
+    }
+}
+
+/// Trigger this event to replace the notes in the sequencer with a sequence.
+#[derive(Event, Debug)]
+pub struct LoadSequence(pub Sequence);
+
+fn load_sequence(
+    trigger: Trigger<LoadSequence>,
+    mut commands: Commands,
+    sequencer: Option<ResMut<Sequencer>>,
+    tracks: Query<(Entity, &Track)>,
+    seq_state: Option<Res<State<SequencerState>>>,
+    mut seq_state_next: ResMut<NextState<SequencerState>>,
+) {
+    let (Some(mut sequencer), Some(seq_state)) = (sequencer, seq_state) else {
+        warn!("Tried to load a sequence outside of the playing screen");
+        return;
+    };
+    let sequence = &trigger.event().0;
+
+    if sequence.length != sequencer.length {
+        warn!(
+            "Sequence is {} seconds long, but the tape is {} seconds",
+            sequence.length, sequencer.length
+        );
+    }
+
+    for id in sequencer.notes.drain(..) {
+        commands.entity(id).despawn_recursive();
+    }
+
+    for (track_id, track) in &tracks {
+        commands.entity(track_id).with_children(|children| {
+            for note in sequence.notes_of(track.robot, track.kind) {
+                let id = Note::spawn(children, *track, note.pos, note.width).id();
+                sequencer.notes.push(id);
+            }
+        });
+    }
+
+    // Replay the loaded notes from the start
+    if *seq_state.get() == SequencerState::Stopped {
+        seq_state_next.set(SequencerState::Playing);
+    } else {
+        sequencer.rewind_to_edit(0.0, seq_state.get(), &mut seq_state_next);
+    }
+}
+
+/// Identifier of the spawned level in the LDtk project.
+#[cfg(feature = "dev_native")]
+fn level_identifier(
+    level_iid: &Query<&LevelIid>,
+    ldtk_handles: &HandleMap<LdtkKey>,
+    projects: &Assets<LdtkProject>,
+) -> Option<String> {
+    let iid = level_iid.iter().next()?;
+    let project = projects.get(&ldtk_handles[&LdtkKey::Level])?;
+    let level = project.get_raw_level_by_iid(iid.get())?;
+    Some(level.identifier.clone())
+}
+
+#[cfg(feature = "dev_native")]
+fn save_solution(
+    sequencer: Res<Sequencer>,
+    notes: Query<&Note>,
+    level_iid: Query<&LevelIid>,
+    ldtk_handles: Res<HandleMap<LdtkKey>>,
+    projects: Res<Assets<LdtkProject>>,
+) {
+    let Some(identifier) = level_identifier(&level_iid, &ldtk_handles, &projects) else {
+        warn!("No level is spawned");
+        return;
+    };
+    let path = solution_path(&identifier);
+    match sequencer.capture(&notes).save(&path) {
+        Ok(()) => info!("Saved solution to {path}"),
+        Err(e) => error!("Failed to save solution to {path}: {e}"),
+    }
+}
+
+#[cfg(feature = "dev_native")]
+fn load_solution(
+    mut commands: Commands,
+    level_iid: Query<&LevelIid>,
+    ldtk_handles: Res<HandleMap<LdtkKey>>,
+    projects: Res<Assets<LdtkProject>>,
+) {
+    let Some(identifier) = level_identifier(&level_iid, &ldtk_handles, &projects) else {
+        warn!("No level is spawned");
+        return;
+    };
+    let path = solution_path(&identifier);
+    match Sequence::load(&path) {
+        Ok(sequence) => commands.trigger(LoadSequence(sequence)),
+        Err(e) => error!("Failed to load solution from {path}: {e}"),
+    }
+}
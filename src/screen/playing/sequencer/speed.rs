@@ -0,0 +1,134 @@
+//! Playback speed of the tape.
+//!
+//! The speed scales Bevy's virtual time, which the fixed timestep follows. The tape, physics
+//! and the robot all advance in fixed steps of the same length whatever the speed, only more or
+//! fewer of them per second, so a sequence plays out identically at every speed.
+
+use bevy::prelude::*;
+
+use crate::screen::Screen;
+use crate::settings::Settings;
+use crate::ui::prelude::*;
+
+pub(super) fn plugin(app: &mut App) {
+    app.register_type::<SpeedAction>();
+    app.add_systems(OnEnter(Screen::Playing), apply_playback_speed);
+    app.add_systems(OnExit(Screen::Playing), reset_playback_speed);
+    app.add_systems(
+        Update,
+        (
+            (speed_keys, handle_speed_action),
+            (apply_playback_speed, update_speed_button).run_if(resource_changed::<Settings>),
+        )
+            .chain()
+            .run_if(in_state(Screen::Playing)),
+    );
+}
+
+/// Speeds the tape can be played at, relative to real time.
+const SPEEDS: [f32; 5] = [0.25, 0.5, 1.0, 2.0, 4.0];
+
+/// The next speed in [`SPEEDS`] slower (`-1`) or faster (`1`) than `speed`, if any.
+fn next_speed(speed: f32, direction: isize) -> Option<f32> {
+    if direction > 0 {
+        SPEEDS.iter().find(|&&s| s > speed).copied()
+    } else {
+        SPEEDS.iter().rev().find(|&&s| s < speed).copied()
+    }
+}
+
+#[derive(Component, Debug, Clone, Copy, PartialEq, Eq, Reflect)]
+#[reflect(Component)]
+enum SpeedAction {
+    /// Go to the next faster speed, or back to the slowest.
+    Cycle,
+}
+
+fn speed_button_text(settings: &Settings) -> String {
+    format!("{}x", settings.playback_speed)
+}
+
+/// Spawns the button to change the playback speed.
+pub(super) fn spawn_speed_button(spawner: &mut ChildBuilder, settings: &Settings) {
+    spawner
+        .small_button(speed_button_text(settings))
+        .insert(SpeedAction::Cycle);
+}
+
+fn speed_keys(keys: Res<ButtonInput<KeyCode>>, mut settings: ResMut<Settings>) {
+    let bindings = &settings.key_bindings;
+    let direction = if keys.just_pressed(bindings.speed_down) {
+        -1
+    } else if keys.just_pressed(bindings.speed_up) {
+        1
+    } else {
+        return;
+    };
+    if let Some(speed) = next_speed(settings.playback_speed, direction) {
+        settings.playback_speed = speed;
+    }
+}
+
+fn handle_speed_action(
+    mut button_query: InteractionQuery<&SpeedAction>,
+    mut settings: ResMut<Settings>,
+) {
+    for (interaction, action) in &mut button_query {
+        if matches!(interaction, Interaction::Pressed) {
+            match action {
+                SpeedAction::Cycle => {
+                    settings.playback_speed =
+                        next_speed(settings.playback_speed, 1).unwrap_or(SPEEDS[0]);
+                }
+            }
+        }
+    }
+}
+
+fn apply_playback_speed(settings: Res<Settings>, mut time: ResMut<Time<Virtual>>) {
+    let speed = settings
+        .playback_speed
+        .clamp(SPEEDS[0], SPEEDS[SPEEDS.len() - 1]);
+    time.set_relative_speed(speed);
+}
+
+// The menus run at normal speed
+fn reset_playback_speed(mut time: ResMut<Time<Virtual>>) {
+    time.set_relative_speed(1.0);
+}
+
+fn update_speed_button(
+    settings: Res<Settings>,
+    button_query: Query<&Children, With<SpeedAction>>,
+    mut text_query: Query<&mut Text>,
+) {
+    for children in &button_query {
+        if let Ok(mut text) = text_query.get_mut(children[0]) {
+            text.sections[0].value = speed_button_text(&settings);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn steps_through_the_speeds() {
+        assert_eq!(next_speed(1.0, 1), Some(2.0));
+        assert_eq!(next_speed(1.0, -1), Some(0.5));
+    }
+
+    #[test]
+    fn stops_at_the_ends() {
+        assert_eq!(next_speed(4.0, 1), None);
+        assert_eq!(next_speed(0.25, -1), None);
+    }
+
+    #[test]
+    fn steps_from_speeds_not_in_the_list() {
+        // e.g. edited in the save file
+        assert_eq!(next_speed(0.75, 1), Some(1.0));
+        assert_eq!(next_speed(0.75, -1), Some(0.5));
+    }
+}
@@ -0,0 +1,420 @@
+//! The part of the tape shown on the tracks.
+//!
+//! The tape of a level can be longer than fits on the tracks, so the tracks show a view of it
+//! that is zoomed with Ctrl and the mouse wheel, and scrolled with the wheel alone.
+//! The view follows the play position, and a ruler above the tracks shows the time.
+
+use bevy::color::palettes::css::WHITE;
+use bevy::color::palettes::tailwind::BLUE_200;
+use bevy::input::mouse::{MouseScrollUnit, MouseWheel};
+use bevy::prelude::*;
+use bevy::ui::{RelativeCursorPosition, UiSystem, Val::*};
+use bevy_ecs_ldtk::prelude::*;
+
+use crate::game::assets::{HandleMap, LdtkKey};
+use crate::screen::Screen;
+use crate::settings::Settings;
+
+use super::notes::NoteDragged;
+use super::{grid_step, Note, Sequencer, Track, DEFAULT_LENGTH, TRACK_WIDTH};
+
+pub(super) fn plugin(app: &mut App) {
+    app.add_systems(
+        Update,
+        (apply_level_length, scroll_timeline, follow_play_pos)
+            .chain()
+            .run_if(in_state(Screen::Playing).and_then(resource_exists::<Timeline>)),
+    );
+    app.add_systems(
+        PostUpdate,
+        (update_grid_lines, update_ruler, layout_notes)
+            .run_if(
+                in_state(Screen::Playing)
+                    .and_then(resource_exists::<Timeline>)
+                    .and_then(resource_changed::<Timeline>.or_else(resource_changed::<Settings>)),
+            )
+            .before(UiSystem::Layout),
+    );
+}
+
+/// Which part of the tape is shown on the tracks, and how zoomed in it is.
+/// Converts between seconds on the tape and pixels on a track.
+#[derive(Resource, Debug, Clone, Copy)]
+pub(super) struct Timeline {
+    /// Pixels per second of tape.
+    zoom: f32,
+    /// Position on the tape at the left edge of the tracks, in seconds.
+    scroll: f32,
+}
+
+// Seconds of tape shown when zoomed in the most.
+const MIN_VIEW_LENGTH: f32 = 1.0;
+
+// How much a line of the mouse wheel zooms.
+const ZOOM_STEP: f32 = 1.2;
+
+// How far a line of the mouse wheel scrolls, in pixels.
+const SCROLL_STEP: f32 = 40.0;
+
+// Fraction of the view kept before the play position when following it.
+const FOLLOW_MARGIN: f32 = 0.1;
+
+impl Timeline {
+    /// A view of the start of a tape `length` seconds long, showing as much of it as
+    /// a tape of the default length would.
+    pub(super) fn new(length: f32) -> Self {
+        Self {
+            zoom: TRACK_WIDTH / length.min(DEFAULT_LENGTH),
+            scroll: 0.0,
+        }
+    }
+
+    /// Position on a track in pixels of `time` seconds on the tape.
+    pub(super) fn to_px(self, time: f32) -> f32 {
+        (time - self.scroll) * self.zoom
+    }
+
+    /// Position on the tape in seconds of `px` pixels on a track.
+    pub(super) fn to_time(self, px: f32) -> f32 {
+        px / self.zoom + self.scroll
+    }
+
+    /// Width in pixels of `duration` seconds.
+    pub(super) fn duration_to_px(&self, duration: f32) -> f32 {
+        duration * self.zoom
+    }
+
+    /// Duration in seconds of `px` pixels.
+    pub(super) fn px_to_duration(&self, px: f32) -> f32 {
+        px / self.zoom
+    }
+
+    /// Positions a note spanning `pos..pos + width` seconds on its track.
+    pub(super) fn place(&self, style: &mut Style, pos: f32, width: f32) {
+        style.left = Px(self.to_px(pos));
+        style.width = Px(self.duration_to_px(width));
+    }
+
+    /// Seconds of tape shown on the tracks.
+    fn view_length(&self) -> f32 {
+        TRACK_WIDTH / self.zoom
+    }
+
+    /// Whether `time` seconds on the tape is shown on the tracks.
+    pub(super) fn shows(&self, time: f32) -> bool {
+        (self.scroll..=self.scroll + self.view_length()).contains(&time)
+    }
+
+    /// Scrolls the view so that `time` is shown, paging to it if it's out of view.
+    pub(super) fn reveal(&mut self, time: f32, length: f32) {
+        if time < self.scroll {
+            self.scroll = time - self.view_length() * (1.0 - FOLLOW_MARGIN);
+        } else if time > self.scroll + self.view_length() {
+            self.scroll = time - self.view_length() * FOLLOW_MARGIN;
+        }
+        self.clamp(length);
+    }
+
+    /// Zooms in by `factor`, or out if less than 1, keeping `time` at the same place.
+    fn zoom_at(&mut self, factor: f32, time: f32, length: f32) {
+        let px = self.to_px(time);
+        self.zoom = (self.zoom * factor).clamp(TRACK_WIDTH / length, TRACK_WIDTH / MIN_VIEW_LENGTH);
+        self.scroll = time - px / self.zoom;
+        self.clamp(length);
+    }
+
+    /// Keeps the view within a tape `length` seconds long, and the whole tape in view
+    /// when zoomed out the most.
+    fn clamp(&mut self, length: f32) {
+        self.zoom = self.zoom.max(TRACK_WIDTH / length);
+        self.scroll = self
+            .scroll
+            .clamp(0.0, (length - self.view_length()).max(0.0));
+    }
+}
+
+/// Identifier of the level field holding the length of the tape in seconds.
+/// Levels without it have a tape of [`DEFAULT_LENGTH`].
+const LENGTH_FIELD: &str = "SequenceLength";
+
+// Each level has its own tape length
+fn apply_level_length(
+    mut level_events: EventReader<LevelEvent>,
+    ldtk_handles: Res<HandleMap<LdtkKey>>,
+    projects: Res<Assets<LdtkProject>>,
+    mut sequencer: ResMut<Sequencer>,
+    mut timeline: ResMut<Timeline>,
+) {
+    for event in level_events.read() {
+        let LevelEvent::Spawned(iid) = event else {
+            continue;
+        };
+        let Some(level) = projects
+            .get(&ldtk_handles[&LdtkKey::Level])
+            .and_then(|project| project.get_raw_level_by_iid(iid.get()))
+        else {
+            continue;
+        };
+
+        let length = match level.get_float_field(LENGTH_FIELD) {
+            Ok(&length) if length >= MIN_VIEW_LENGTH => length,
+            Ok(&length) => {
+                warn!("Level {} has a tape of {length} seconds", level.identifier);
+                DEFAULT_LENGTH
+            }
+            Err(_) => DEFAULT_LENGTH,
+        };
+        sequencer.length = length;
+        sequencer.play_pos = sequencer.play_pos.min(length);
+        sequencer.seek_target = sequencer.seek_target.min(length);
+        *timeline = Timeline::new(length);
+    }
+}
+
+// Ctrl and the mouse wheel zooms around the cursor, and the wheel alone scrolls.
+fn scroll_timeline(
+    mut wheel: EventReader<MouseWheel>,
+    keys: Res<ButtonInput<KeyCode>>,
+    hovered: Query<&RelativeCursorPosition, Or<(With<Track>, With<TimeRuler>)>>,
+    sequencer: Res<Sequencer>,
+    mut timeline: ResMut<Timeline>,
+) {
+    // only while the cursor is over the tracks or the ruler
+    let Some(cursor_x) = hovered
+        .iter()
+        .filter(|cursor| cursor.mouse_over())
+        .find_map(|cursor| cursor.normalized)
+        .map(|pos| pos.x * TRACK_WIDTH)
+    else {
+        wheel.clear();
+        return;
+    };
+    let zooming = keys.any_pressed([KeyCode::ControlLeft, KeyCode::ControlRight]);
+
+    for event in wheel.read() {
+        let lines = match event.unit {
+            MouseScrollUnit::Line => event.y,
+            MouseScrollUnit::Pixel => event.y / SCROLL_STEP,
+        };
+        if zooming {
+            let time = timeline.to_time(cursor_x);
+            timeline.zoom_at(ZOOM_STEP.powf(lines), time, sequencer.length);
+        } else {
+            // the wheel down scrolls to later in the tape
+            timeline.scroll -= timeline.px_to_duration(lines * SCROLL_STEP);
+            timeline.clamp(sequencer.length);
+        }
+    }
+}
+
+// Pages the view along with the play position, unless the player scrolled away from it
+fn follow_play_pos(
+    sequencer: Res<Sequencer>,
+    mut timeline: ResMut<Timeline>,
+    mut was_shown: Local<bool>,
+) {
+    let shown = timeline.shows(sequencer.play_pos);
+    if !shown && *was_shown {
+        timeline.reveal(sequencer.play_pos, sequencer.length);
+    } else {
+        *was_shown = shown;
+    }
+}
+
+/// Shows the time above the tracks.
+#[derive(Component)]
+pub(super) struct TimeRuler;
+
+// Labels on the ruler are at least this far apart, in pixels.
+const MIN_LABEL_SPACING: f32 = 50.0;
+
+pub(super) fn spawn_ruler(spawner: &mut ChildBuilder) {
+    // the tracks are labeled in the first column
+    spawner.spawn(NodeBundle::default());
+    spawner.spawn((
+        Name::new("Time Ruler"),
+        NodeBundle {
+            style: Style {
+                width: Px(TRACK_WIDTH),
+                height: Px(20.0),
+                overflow: Overflow::clip(),
+                ..default()
+            },
+            ..default()
+        },
+        TimeRuler,
+        RelativeCursorPosition::default(),
+    ));
+}
+
+fn update_ruler(
+    mut commands: Commands,
+    rulers: Query<Entity, With<TimeRuler>>,
+    sequencer: Res<Sequencer>,
+    timeline: Res<Timeline>,
+) {
+    // Label every second, or every few seconds when zoomed out
+    let Some(&interval) = [0.5, 1.0, 2.0, 5.0, 10.0, 30.0]
+        .iter()
+        .find(|&&interval| timeline.duration_to_px(interval) >= MIN_LABEL_SPACING)
+    else {
+        return;
+    };
+
+    for ruler in &rulers {
+        commands
+            .entity(ruler)
+            .despawn_descendants()
+            .with_children(|children| {
+                let first = (timeline.scroll / interval).ceil() as u32;
+                for i in first.. {
+                    let time = i as f32 * interval;
+                    if time > sequencer.length || !timeline.shows(time) {
+                        break;
+                    }
+                    children.spawn(TextBundle {
+                        text: Text::from_section(
+                            format!("{time}s"),
+                            TextStyle {
+                                font_size: 16.0,
+                                color: WHITE.into(),
+                                ..default()
+                            },
+                        ),
+                        style: Style {
+                            position_type: PositionType::Absolute,
+                            left: Px(timeline.to_px(time)),
+                            ..default()
+                        },
+                        ..default()
+                    });
+                }
+            });
+    }
+}
+
+#[derive(Component)]
+struct GridLine;
+
+// Every this many grid lines is drawn brighter.
+const GRID_LINES_PER_BEAT: u32 = 4;
+
+fn update_grid_lines(
+    mut commands: Commands,
+    grid_lines: Query<Entity, With<GridLine>>,
+    tracks: Query<Entity, With<Track>>,
+    settings: Res<Settings>,
+    sequencer: Res<Sequencer>,
+    timeline: Res<Timeline>,
+) {
+    for line in &grid_lines {
+        commands.entity(line).despawn_recursive();
+    }
+
+    let step = grid_step(&settings);
+    let first = (timeline.scroll / step).ceil().max(1.0) as u32;
+    for track in &tracks {
+        commands.entity(track).with_children(|children| {
+            for i in first.. {
+                let time = i as f32 * step;
+                if time >= sequencer.length || !timeline.shows(time) {
+                    break;
+                }
+                let color = if i % GRID_LINES_PER_BEAT == 0 {
+                    BLUE_200.with_alpha(0.5)
+                } else {
+                    BLUE_200.with_alpha(0.2)
+                };
+                children.spawn((
+                    Name::new("Grid Line"),
+                    NodeBundle {
+                        style: Style {
+                            position_type: PositionType::Absolute,
+                            left: Px(timeline.to_px(time)),
+                            width: Px(1.0),
+                            height: Percent(100.0),
+                            ..default()
+                        },
+                        background_color: color.into(),
+                        // behind the notes
+                        z_index: ZIndex::Local(-1),
+                        ..default()
+                    },
+                    GridLine,
+                ));
+            }
+        });
+    }
+}
+
+// Moves the notes along with the view. Notes added are placed in `notes::set_initial_note_pos`.
+fn layout_notes(
+    mut notes: Query<(Entity, &Note, &mut Style)>,
+    note_drag: Option<Res<NoteDragged>>,
+    timeline: Res<Timeline>,
+) {
+    for (entity, note, mut style) in &mut notes {
+        // dragged notes stay where they are dragged to, not where they were
+        let pos = note_drag
+            .as_deref()
+            .and_then(|note_drag| note_drag.dragged_pos(entity, note.pos))
+            .unwrap_or(note.pos);
+        timeline.place(&mut style, pos, note.width);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // A tape four times as long as the view
+    const LENGTH: f32 = DEFAULT_LENGTH * 4.0;
+
+    #[test]
+    fn shows_as_much_as_the_default_tape() {
+        let timeline = Timeline::new(LENGTH);
+        assert_eq!(timeline.view_length(), DEFAULT_LENGTH);
+        assert_eq!(timeline.to_px(DEFAULT_LENGTH), TRACK_WIDTH);
+
+        // shorter tapes fill the tracks
+        let timeline = Timeline::new(DEFAULT_LENGTH / 2.0);
+        assert_eq!(timeline.to_px(DEFAULT_LENGTH / 2.0), TRACK_WIDTH);
+    }
+
+    #[test]
+    fn converts_back_and_forth_when_scrolled() {
+        let mut timeline = Timeline::new(LENGTH);
+        timeline.scroll = 3.0;
+        assert_eq!(timeline.to_px(3.0), 0.0);
+        assert_eq!(timeline.to_time(timeline.to_px(5.5)), 5.5);
+        assert_eq!(
+            timeline.to_px(5.5) - timeline.to_px(4.5),
+            timeline.duration_to_px(1.0)
+        );
+    }
+
+    #[test]
+    fn reveals_times_out_of_view() {
+        let mut timeline = Timeline::new(LENGTH);
+        timeline.reveal(DEFAULT_LENGTH / 2.0, LENGTH);
+        assert_eq!(timeline.scroll, 0.0, "already shown");
+
+        let later = DEFAULT_LENGTH * 2.0;
+        timeline.reveal(later, LENGTH);
+        assert!(timeline.shows(later));
+        assert!(timeline.scroll > DEFAULT_LENGTH);
+
+        timeline.reveal(1.0, LENGTH);
+        assert!(timeline.shows(1.0));
+    }
+
+    #[test]
+    fn reveals_within_the_tape() {
+        let mut timeline = Timeline::new(LENGTH);
+        timeline.reveal(LENGTH, LENGTH);
+        assert_eq!(timeline.scroll, LENGTH - DEFAULT_LENGTH);
+
+        timeline.reveal(0.0, LENGTH);
+        assert_eq!(timeline.scroll, 0.0);
+    }
+}
@@ -0,0 +1,225 @@
+//! Transport controls of the tape: play, pause, stop, restart and stepping.
+//!
+//! Each control is a button and a key (see [`KeyBindings`](crate::settings::KeyBindings)).
+//! Stepping moves the tape to the previous or next grid line and pauses there,
+//! or by a single frame while the snap bypass key is held.
+
+use bevy::prelude::*;
+use bevy::ui::Val::*;
+
+use crate::screen::{playing::SequencerState, Screen};
+use crate::settings::{modifier_pressed, Settings};
+use crate::ui::prelude::*;
+
+use super::{grid_step, NoteCreation, Sequencer, TapeRestarted};
+
+pub(super) fn plugin(app: &mut App) {
+    app.register_type::<TransportAction>();
+    app.add_systems(
+        Update,
+        (transport_keys, handle_transport_action).run_if(
+            in_state(Screen::Playing)
+                .and_then(resource_exists::<Sequencer>)
+                .and_then(not(in_state(SequencerState::Cleared)))
+                .and_then(not(resource_exists::<NoteCreation>)),
+        ),
+    );
+}
+
+#[derive(Component, Debug, Clone, Copy, PartialEq, Eq, Reflect)]
+#[reflect(Component)]
+pub(super) enum TransportAction {
+    Play,
+    Pause,
+    Stop,
+    Restart,
+    StepBack,
+    StepForward,
+}
+
+fn transport_keys(
+    mut commands: Commands,
+    keys: Res<ButtonInput<KeyCode>>,
+    settings: Res<Settings>,
+    mut sequencer: ResMut<Sequencer>,
+    fixed_time: Res<Time<Fixed>>,
+    seq_state: Res<State<SequencerState>>,
+    mut seq_state_next: ResMut<NextState<SequencerState>>,
+) {
+    let bindings = &settings.key_bindings;
+    // Ctrl is held for the clipboard and history shortcuts
+    if keys.any_pressed([KeyCode::ControlLeft, KeyCode::ControlRight]) {
+        return;
+    }
+
+    let action = if keys.just_pressed(bindings.play_pause) {
+        match seq_state.get() {
+            SequencerState::Playing | SequencerState::Seeking => TransportAction::Pause,
+            _ => TransportAction::Play,
+        }
+    } else if keys.just_pressed(bindings.stop) {
+        TransportAction::Stop
+    } else if keys.just_pressed(bindings.restart) {
+        TransportAction::Restart
+    } else if keys.just_pressed(bindings.step_back) {
+        TransportAction::StepBack
+    } else if keys.just_pressed(bindings.step_forward) {
+        TransportAction::StepForward
+    } else {
+        return;
+    };
+
+    apply_transport_action(
+        &mut commands,
+        action,
+        step_length(&keys, &settings, &fixed_time),
+        &mut sequencer,
+        &seq_state,
+        &mut seq_state_next,
+    );
+}
+
+fn handle_transport_action(
+    mut commands: Commands,
+    mut button_query: InteractionQuery<&TransportAction>,
+    keys: Res<ButtonInput<KeyCode>>,
+    settings: Res<Settings>,
+    mut sequencer: ResMut<Sequencer>,
+    fixed_time: Res<Time<Fixed>>,
+    seq_state: Res<State<SequencerState>>,
+    mut seq_state_next: ResMut<NextState<SequencerState>>,
+) {
+    for (interaction, &action) in &mut button_query {
+        if matches!(interaction, Interaction::Pressed) {
+            apply_transport_action(
+                &mut commands,
+                action,
+                step_length(&keys, &settings, &fixed_time),
+                &mut sequencer,
+                &seq_state,
+                &mut seq_state_next,
+            );
+        }
+    }
+}
+
+/// How far the tape is stepped, in seconds.
+#[derive(Clone, Copy, Debug)]
+enum Step {
+    /// To the previous or next grid line, this many seconds apart.
+    Grid(f32),
+    /// By a single fixed timestep, of this many seconds.
+    Frame(f32),
+}
+
+fn step_length(keys: &ButtonInput<KeyCode>, settings: &Settings, fixed_time: &Time<Fixed>) -> Step {
+    if modifier_pressed(keys, settings.key_bindings.bypass_snap) {
+        Step::Frame(fixed_time.timestep().as_secs_f32())
+    } else {
+        Step::Grid(grid_step(settings))
+    }
+}
+
+fn apply_transport_action(
+    commands: &mut Commands,
+    action: TransportAction,
+    step: Step,
+    sequencer: &mut Sequencer,
+    seq_state: &State<SequencerState>,
+    seq_state_next: &mut NextState<SequencerState>,
+) {
+    let state = seq_state.get();
+    match action {
+        TransportAction::Play => match state {
+            // the world may have moved while stopped, so restore it first
+            SequencerState::Stopped => {
+                let play_pos = sequencer.play_pos;
+                sequencer.seek(play_pos, seq_state_next);
+            }
+            SequencerState::Paused => seq_state_next.set(SequencerState::Playing),
+            SequencerState::Playing => sequencer.pause_at = None,
+            SequencerState::Seeking => sequencer.after_seek = SequencerState::Playing,
+            SequencerState::Cleared => {}
+        },
+        TransportAction::Pause => match state {
+            SequencerState::Playing => seq_state_next.set(SequencerState::Paused),
+            // finish rewinding the world first
+            SequencerState::Seeking => sequencer.after_seek = SequencerState::Paused,
+            _ => {}
+        },
+        TransportAction::Stop => {
+            commands.trigger(TapeRestarted);
+            sequencer.seek_and(0.0, SequencerState::Stopped, seq_state_next);
+        }
+        TransportAction::Restart => {
+            commands.trigger(TapeRestarted);
+            sequencer.seek(0.0, seq_state_next);
+        }
+        TransportAction::StepBack => {
+            if *state == SequencerState::Seeking {
+                return;
+            }
+            let target = match step {
+                // Don't step back to the grid line the tape is already on
+                Step::Grid(grid) => {
+                    (((sequencer.play_pos - STEP_TOLERANCE) / grid).ceil() - 1.0) * grid
+                }
+                Step::Frame(frame) => sequencer.play_pos - frame,
+            };
+            sequencer.seek_and(target.max(0.0), SequencerState::Paused, seq_state_next);
+        }
+        TransportAction::StepForward => {
+            let target = match step {
+                Step::Grid(grid) => {
+                    (((sequencer.play_pos + STEP_TOLERANCE) / grid).floor() + 1.0) * grid
+                }
+                Step::Frame(frame) => sequencer.play_pos + frame,
+            };
+            let target = target.min(sequencer.length);
+            match state {
+                SequencerState::Stopped => {
+                    let play_pos = sequencer.play_pos;
+                    sequencer.seek(play_pos, seq_state_next);
+                }
+                SequencerState::Paused => seq_state_next.set(SequencerState::Playing),
+                SequencerState::Playing => {}
+                SequencerState::Seeking | SequencerState::Cleared => return,
+            }
+            sequencer.pause_at = Some(target);
+        }
+    }
+}
+
+// How far from a grid line the tape is still on it, for rounding errors, in seconds.
+const STEP_TOLERANCE: f32 = 1e-4;
+
+/// Spawns the transport buttons.
+pub(super) fn spawn_transport_buttons(spawner: &mut ChildBuilder) {
+    spawner
+        .spawn((
+            Name::new("Transport"),
+            NodeBundle {
+                style: Style {
+                    column_gap: Px(10.0),
+                    ..default()
+                },
+                ..default()
+            },
+        ))
+        .with_children(|children| {
+            children.small_button("Play").insert(TransportAction::Play);
+            children
+                .small_button("Pause")
+                .insert(TransportAction::Pause);
+            children.small_button("Stop").insert(TransportAction::Stop);
+            children
+                .small_button("Restart")
+                .insert(TransportAction::Restart);
+            children
+                .small_button("Step <")
+                .insert(TransportAction::StepBack);
+            children
+                .small_button("Step >")
+                .insert(TransportAction::StepForward);
+        });
+}
@@ -3,31 +3,44 @@
 use bevy::prelude::*;
 
 use super::Screen;
-use crate::ui::prelude::*;
+use crate::{settings::Settings, ui::prelude::*};
 
 pub(super) fn plugin(app: &mut App) {
     app.add_systems(OnEnter(Screen::Title), enter_title);
 
     app.register_type::<TitleAction>();
     app.add_systems(Update, handle_title_action.run_if(in_state(Screen::Title)));
+    app.add_systems(
+        Update,
+        update_settings_buttons
+            .run_if(in_state(Screen::Title).and_then(resource_changed::<Settings>)),
+    );
 }
 
 #[derive(Component, Debug, Clone, Copy, PartialEq, Eq, Reflect)]
 #[reflect(Component)]
 enum TitleAction {
     Play,
+    ToggleGlitch,
+    CycleOverlapMode,
     Credits,
     /// Exit doesn't work well with embedded applications.
     #[cfg(not(target_family = "wasm"))]
     Exit,
 }
 
-fn enter_title(mut commands: Commands) {
+fn enter_title(mut commands: Commands, settings: Res<Settings>) {
     commands
         .ui_root()
         .insert(StateScoped(Screen::Title))
         .with_children(|children| {
             children.button("Play").insert(TitleAction::Play);
+            children
+                .button(glitch_button_text(&settings))
+                .insert(TitleAction::ToggleGlitch);
+            children
+                .button(overlap_button_text(&settings))
+                .insert(TitleAction::CycleOverlapMode);
             children.button("Credits").insert(TitleAction::Credits);
 
             #[cfg(not(target_family = "wasm"))]
@@ -38,12 +51,17 @@ fn enter_title(mut commands: Commands) {
 fn handle_title_action(
     mut next_screen: ResMut<NextState<Screen>>,
     mut button_query: InteractionQuery<&TitleAction>,
+    mut settings: ResMut<Settings>,
     #[cfg(not(target_family = "wasm"))] mut app_exit: EventWriter<AppExit>,
 ) {
     for (interaction, action) in &mut button_query {
         if matches!(interaction, Interaction::Pressed) {
             match action {
-                TitleAction::Play => next_screen.set(Screen::Playing),
+                TitleAction::Play => next_screen.set(Screen::LevelSelect),
+                TitleAction::ToggleGlitch => settings.glitch_effect = !settings.glitch_effect,
+                TitleAction::CycleOverlapMode => {
+                    settings.overlap_mode = settings.overlap_mode.next();
+                }
                 TitleAction::Credits => next_screen.set(Screen::Credits),
 
                 #[cfg(not(target_family = "wasm"))]
@@ -54,3 +72,32 @@ fn handle_title_action(
         }
     }
 }
+
+fn glitch_button_text(settings: &Settings) -> &'static str {
+    if settings.glitch_effect {
+        "Glitch: On"
+    } else {
+        "Glitch: Off"
+    }
+}
+
+fn overlap_button_text(settings: &Settings) -> String {
+    format!("Overlap: {}", settings.overlap_mode.label())
+}
+
+fn update_settings_buttons(
+    settings: Res<Settings>,
+    button_query: Query<(&TitleAction, &Children)>,
+    mut text_query: Query<&mut Text>,
+) {
+    for (action, children) in &button_query {
+        let value = match action {
+            TitleAction::ToggleGlitch => glitch_button_text(&settings).to_string(),
+            TitleAction::CycleOverlapMode => overlap_button_text(&settings),
+            _ => continue,
+        };
+        if let Ok(mut text) = text_query.get_mut(children[0]) {
+            text.sections[0].value = value;
+        }
+    }
+}
@@ -0,0 +1,170 @@
+//! User settings.
+
+use bevy::{audio::Volume, prelude::*};
+use serde::{Deserialize, Serialize};
+
+pub(super) fn plugin(app: &mut App) {
+    app.register_type::<Settings>();
+    app.init_resource::<Settings>();
+
+    app.add_systems(Update, apply_volume.run_if(resource_changed::<Settings>));
+}
+
+#[derive(Resource, Debug, Clone, PartialEq, Reflect, Serialize, Deserialize)]
+#[reflect(Resource)]
+// Settings added later are filled with defaults when loading an older save
+#[serde(default)]
+pub struct Settings {
+    /// Show the VHS glitch effect while the tape is seeking.
+    pub glitch_effect: bool,
+    /// Global volume, from `0.0` to `1.0`.
+    pub volume: f32,
+    /// Number of grid cells the sequencer tape is divided into. Notes snap to the grid lines.
+    pub grid_divisions: u32,
+    pub overlap_mode: OverlapMode,
+    /// Speed the tape plays at, from `0.25` to `4.0` times real time. Doesn't change the results.
+    pub playback_speed: f32,
+    pub key_bindings: KeyBindings,
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Self {
+            glitch_effect: true,
+            volume: 0.3,
+            grid_divisions: 32,
+            overlap_mode: OverlapMode::default(),
+            playback_speed: 1.0,
+            key_bindings: KeyBindings::default(),
+        }
+    }
+}
+
+/// What happens to the other notes on a track when a note is placed over them.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Reflect, Serialize, Deserialize)]
+pub enum OverlapMode {
+    /// Move them out of the way.
+    #[default]
+    Push,
+    /// Cut off the overlapped part, removing them if too little is left.
+    Trim,
+    /// Leave them, and put the placed note back where it was.
+    Reject,
+}
+
+impl OverlapMode {
+    pub fn label(self) -> &'static str {
+        match self {
+            OverlapMode::Push => "Push",
+            OverlapMode::Trim => "Trim",
+            OverlapMode::Reject => "Reject",
+        }
+    }
+
+    /// The next mode, for cycling through them with a button.
+    pub fn next(self) -> Self {
+        match self {
+            OverlapMode::Push => OverlapMode::Trim,
+            OverlapMode::Trim => OverlapMode::Reject,
+            OverlapMode::Reject => OverlapMode::Push,
+        }
+    }
+}
+
+/// Keys for the actions of the game.
+#[derive(Debug, Clone, PartialEq, Reflect, Serialize, Deserialize)]
+#[serde(default)]
+pub struct KeyBindings {
+    /// Leave the level.
+    pub back: KeyCode,
+    /// Hold to place notes freely instead of snapping them to the grid.
+    pub bypass_snap: KeyCode,
+    /// Hold to add notes to the selection or take them out of it by pressing them,
+    /// or to select them with a rubber band dragged from a track.
+    pub extend_selection: KeyCode,
+    /// Hold with `copy`, `paste` or `duplicate` to copy the selected notes, paste them at
+    /// the play position, or duplicate them right after themselves.
+    pub clipboard_modifier: KeyCode,
+    pub copy: KeyCode,
+    pub paste: KeyCode,
+    pub duplicate: KeyCode,
+    /// Turn keyboard editing of the sequencer on or off.
+    pub keyboard_editing: KeyCode,
+    /// Move the edit cursor along the tape, or nudge or resize the note under it.
+    pub cursor_left: KeyCode,
+    pub cursor_right: KeyCode,
+    /// Move the edit cursor to the track above or below.
+    pub track_up: KeyCode,
+    pub track_down: KeyCode,
+    /// Insert a note of the track's kind at the edit cursor.
+    pub insert_note: KeyCode,
+    /// Delete the note under the edit cursor.
+    pub delete_note: KeyCode,
+    /// Hold to move the note under the edit cursor instead of the cursor.
+    pub nudge_note: KeyCode,
+    /// Hold to resize the note under the edit cursor from its end instead of moving the cursor.
+    pub resize_note: KeyCode,
+    /// Pause the tape, or play it from where it was paused or stopped.
+    pub play_pause: KeyCode,
+    /// Stop the tape and rewind it to the start.
+    pub stop: KeyCode,
+    /// Play the tape again from the start.
+    pub restart: KeyCode,
+    /// Step the tape back or forward to the previous or next grid line and pause there,
+    /// or by a single frame while `bypass_snap` is held.
+    pub step_back: KeyCode,
+    pub step_forward: KeyCode,
+    /// Play the tape slower or faster.
+    pub speed_down: KeyCode,
+    pub speed_up: KeyCode,
+}
+
+impl Default for KeyBindings {
+    fn default() -> Self {
+        Self {
+            back: KeyCode::Escape,
+            bypass_snap: KeyCode::AltLeft,
+            extend_selection: KeyCode::ShiftLeft,
+            clipboard_modifier: KeyCode::ControlLeft,
+            copy: KeyCode::KeyC,
+            paste: KeyCode::KeyV,
+            duplicate: KeyCode::KeyD,
+            keyboard_editing: KeyCode::Tab,
+            cursor_left: KeyCode::ArrowLeft,
+            cursor_right: KeyCode::ArrowRight,
+            track_up: KeyCode::ArrowUp,
+            track_down: KeyCode::ArrowDown,
+            insert_note: KeyCode::Enter,
+            delete_note: KeyCode::Delete,
+            nudge_note: KeyCode::AltLeft,
+            resize_note: KeyCode::ControlLeft,
+            play_pause: KeyCode::Space,
+            stop: KeyCode::KeyS,
+            restart: KeyCode::KeyR,
+            step_back: KeyCode::Comma,
+            step_forward: KeyCode::Period,
+            speed_down: KeyCode::Minus,
+            speed_up: KeyCode::Equal,
+        }
+    }
+}
+
+/// Whether `key` is held, or the same modifier on the other side of the keyboard.
+pub fn modifier_pressed(keys: &ButtonInput<KeyCode>, key: KeyCode) -> bool {
+    let other_side = match key {
+        KeyCode::ShiftLeft => KeyCode::ShiftRight,
+        KeyCode::ShiftRight => KeyCode::ShiftLeft,
+        KeyCode::ControlLeft => KeyCode::ControlRight,
+        KeyCode::ControlRight => KeyCode::ControlLeft,
+        KeyCode::AltLeft => KeyCode::AltRight,
+        KeyCode::AltRight => KeyCode::AltLeft,
+        KeyCode::SuperLeft => KeyCode::SuperRight,
+        KeyCode::SuperRight => KeyCode::SuperLeft,
+        key => key,
+    };
+    keys.any_pressed([key, other_side])
+}
+
+fn apply_volume(settings: Res<Settings>, mut global_volume: ResMut<GlobalVolume>) {
+    global_volume.volume = Volume::new(settings.volume);
+}
@@ -11,8 +11,11 @@ pub(super) fn plugin(app: &mut App) {
         Update,
         (
             apply_interaction_palette,
-            trigger_interaction_sfx
-                .run_if(in_state(Screen::Title).or_else(in_state(Screen::Credits))),
+            trigger_interaction_sfx.run_if(
+                in_state(Screen::Title)
+                    .or_else(in_state(Screen::LevelSelect))
+                    .or_else(in_state(Screen::Credits)),
+            ),
         ),
     );
 }
@@ -0,0 +1,42 @@
+//! Every checked-in solution still gets the robot to the goal of its level.
+
+use std::time::Duration;
+
+use bevy_jam_5::headless::{simulate, simulate_at, solution_path, solved_levels, Sequence};
+
+#[test]
+fn solutions_reach_the_goal() {
+    let levels = solved_levels().expect("solutions should be listed");
+    assert!(!levels.is_empty(), "no solutions to check");
+
+    for level in &levels {
+        let sequence = Sequence::load(solution_path(level)).expect("solution should load");
+        let report = simulate(level, &sequence).expect("level should be simulated");
+        assert!(report.reached_goal, "{level}: {report:?}");
+    }
+}
+
+#[test]
+fn solutions_play_the_same_at_any_frame_rate() {
+    let levels = solved_levels().expect("solutions should be listed");
+
+    for level in &levels {
+        let sequence = Sequence::load(solution_path(level)).expect("solution should load");
+        let expected = simulate(level, &sequence).expect("level should be simulated");
+        // several fixed steps per update, and updates without any
+        for fps in [15.0, 50.0, 144.0] {
+            let report = simulate_at(level, &sequence, Duration::from_secs_f64(1.0 / fps))
+                .expect("level should be simulated");
+            assert_eq!(
+                report.reached_goal, expected.reached_goal,
+                "{level} at {fps} fps"
+            );
+            assert_eq!(report.elapsed, expected.elapsed, "{level} at {fps} fps");
+            // the solver isn't bit for bit reproducible with several robots in contact
+            assert!(
+                report.final_position.distance(expected.final_position) < 0.01,
+                "{level} at {fps} fps: {report:?}, expected {expected:?}"
+            );
+        }
+    }
+}